@@ -0,0 +1,520 @@
+use crate::data::{Channel, ChannelKind, Column, DataLine, FileContents, LineMetadata, LineProblems, LoadSettings, ReadProblem, Time};
+use std::fs::File;
+use std::convert::TryInto;
+use std::io::{BufReader, BufWriter, Read, Seek, Write};
+use std::time::UNIX_EPOCH;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+const MAGIC: &[u8] = b"MUNINN-CACHE-4\n";
+
+/// Smaller files are quick enough to parse, and not worth cluttering their directory for
+const MIN_CACHED_SIZE: u64 = 1024 * 1024;
+
+/// Name of the sidecar cache file of a data file
+fn cache_filename(filename: &str) -> String {
+    format!("{}.muninn-cache", filename)
+}
+
+/// Identifies the state of a source file. A cache is only valid for the exact file it was made from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SourceKey {
+    length: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl SourceKey {
+    pub fn of(filename: &str) -> Result<SourceKey> {
+        let metadata = std::fs::metadata(filename)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(SourceKey {
+            length: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+/// Reads the cached lines of a file, if there is a cache matching the file as it is now and the
+/// settings it is to be read with
+pub fn read_cache(filename: &str, settings: &LoadSettings) -> Option<FileContents> {
+    let key = SourceKey::of(filename).ok()?;
+    let file = File::open(cache_filename(filename)).ok()?;
+    let length = file.metadata().ok()?.len();
+    let mut reader = CacheReader { reader: BufReader::new(file) };
+    reader.read_contents(key, settings, length).ok()?
+}
+
+/// Writes the lines read from a file to its cache. The key should be taken before the file was read,
+/// so a file changing while it is read is not mistaken for the version that was read.
+pub fn write_cache(filename: &str, key: SourceKey, settings: &LoadSettings,
+                   time_line_pairs: &[(Time, DataLine)], problems: &LineProblems) -> Result<()> {
+    if key.length < MIN_CACHED_SIZE {
+        return Ok(());
+    }
+
+    // Written next to the cache and moved into place when complete, so a cache is never read half
+    // written, e.g. by another instance loading the same file
+    let cache = cache_filename(filename);
+    let temporary = format!("{}.{}.tmp", cache, std::process::id());
+    let written = File::create(&temporary).map_err(|e| e.into()).and_then(|file| {
+        let mut writer = CacheWriter { writer: BufWriter::new(file) };
+        writer.write_contents(key, settings, time_line_pairs, problems)?;
+        writer.writer.flush()?;
+        Ok(())
+    });
+    match written.and_then(|_| Ok(std::fs::rename(&temporary, &cache)?)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary);
+            Err(e)
+        },
+    }
+}
+
+/// The settings that change what is read from a file. Others, e.g. whether restarts are merged,
+/// only affect what is done with the lines afterwards.
+fn settings_key(settings: &LoadSettings) -> Result<Vec<u8>> {
+    let mut writer = CacheWriter { writer: Vec::new() };
+    let columns = &settings.columns;
+    writer.optional_column(&columns.time)?;
+    writer.optional_column(&columns.x)?;
+    for list in [&columns.y, &columns.imaginary, &columns.uncertainty].iter() {
+        writer.u64(list.len() as u64)?;
+        for column in list.iter() {
+            writer.column(column)?;
+        }
+    }
+    writer.f64(settings.time_tolerance.relative)?;
+    writer.f64(settings.time_tolerance.absolute)?;
+    writer.optional_f64(settings.time_filter.start)?;
+    writer.optional_f64(settings.time_filter.end)?;
+    writer.u64(settings.time_filter.stride as u64)?;
+    Ok(writer.writer)
+}
+
+/// Layout of a cache file, all numbers little-endian:
+/// - magic, source key, settings that affect reading
+/// - metadata table, since many lines share the same metadata
+/// - for each line its time, metadata index, offset and number of its points, and kinds of channels
+/// - problems found in the file
+/// - for each line its points, packed as pairs of f64, followed by the values of each channel
+struct CacheWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> CacheWriter<W> {
+    fn write_contents(&mut self, key: SourceKey, settings: &LoadSettings,
+                      time_line_pairs: &[(Time, DataLine)], problems: &LineProblems) -> Result<()> {
+        self.writer.write_all(MAGIC)?;
+        self.u64(key.length)?;
+        self.u64(key.modified_secs)?;
+        self.u32(key.modified_nanos)?;
+        // Cached data is only valid for the settings it was read with
+        let settings_key = settings_key(settings)?;
+        self.u64(settings_key.len() as u64)?;
+        self.writer.write_all(&settings_key)?;
+
+        let mut metadata_table: Vec<&LineMetadata> = Vec::new();
+        let mut metadata_indices = Vec::new();
+        for (_, line) in time_line_pairs {
+            let index = match &line.metadata {
+                Some(metadata) => match metadata_table.iter().position(|m| *m == metadata) {
+                    Some(index) => index as u32,
+                    None => {
+                        metadata_table.push(metadata);
+                        metadata_table.len() as u32 - 1
+                    },
+                },
+                None => u32::MAX,
+            };
+            metadata_indices.push(index);
+        }
+        self.u32(metadata_table.len() as u32)?;
+        for metadata in metadata_table {
+            self.metadata(metadata)?;
+        }
+
+        self.u64(time_line_pairs.len() as u64)?;
+        let mut offset = 0; // in f64 values
+        for ((time, line), index) in time_line_pairs.iter().zip(metadata_indices) {
+            self.f64(*time)?;
+            self.u32(index)?;
+            self.u64(offset)?;
+            self.u64(line.points.len() as u64)?;
+            self.u32(line.channels.len() as u32)?;
+            for channel in &line.channels {
+                self.channel_kind(channel.kind)?;
+            }
+            offset += (line.points.len() * (2 + line.channels.len())) as u64;
+        }
+
+        self.u64(problems.len() as u64)?;
+        for (line_number, problem) in problems {
+            self.u64(*line_number as u64)?;
+            self.problem(problem)?;
+        }
+
+        for (_, line) in time_line_pairs {
+            for (x, y) in &line.points {
+                self.f64(*x)?;
+                self.f64(*y)?;
+            }
+            for channel in &line.channels {
+                for value in &channel.values {
+                    self.f64(*value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn u8(&mut self, value: u8) -> Result<()> {
+        self.writer.write_all(&[value])?;
+        Ok(())
+    }
+
+    fn u32(&mut self, value: u32) -> Result<()> {
+        self.writer.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn u64(&mut self, value: u64) -> Result<()> {
+        self.writer.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn f64(&mut self, value: f64) -> Result<()> {
+        self.writer.write_all(&value.to_le_bytes())?;
+        Ok(())
+    }
+
+    fn string(&mut self, value: &str) -> Result<()> {
+        self.u64(value.len() as u64)?;
+        self.writer.write_all(value.as_bytes())?;
+        Ok(())
+    }
+
+    fn optional_string(&mut self, value: &Option<String>) -> Result<()> {
+        match value {
+            Some(s) => {
+                self.u8(1)?;
+                self.string(s)
+            },
+            None => self.u8(0),
+        }
+    }
+
+    fn optional_u32(&mut self, value: Option<u32>) -> Result<()> {
+        match value {
+            Some(v) => {
+                self.u8(1)?;
+                self.u32(v)
+            },
+            None => self.u8(0),
+        }
+    }
+
+    fn optional_f64(&mut self, value: Option<f64>) -> Result<()> {
+        match value {
+            Some(v) => {
+                self.u8(1)?;
+                self.f64(v)
+            },
+            None => self.u8(0),
+        }
+    }
+
+    fn column(&mut self, column: &Column) -> Result<()> {
+        match column {
+            Column::Number(number) => {
+                self.u8(0)?;
+                self.u64(*number as u64)
+            },
+            Column::Name(name) => {
+                self.u8(1)?;
+                self.string(name)
+            },
+        }
+    }
+
+    fn optional_column(&mut self, column: &Option<Column>) -> Result<()> {
+        match column {
+            Some(column) => {
+                self.u8(1)?;
+                self.column(column)
+            },
+            None => self.u8(0),
+        }
+    }
+
+    fn metadata(&mut self, metadata: &LineMetadata) -> Result<()> {
+        self.string(&metadata.axis)?;
+        self.string(&metadata.variable)?;
+        self.optional_u32(metadata.rank)?;
+        self.optional_u32(metadata.grid)?;
+        self.optional_string(&metadata.output_type)?;
+        self.optional_string(&metadata.orientation)?;
+        self.optional_u32(metadata.refinement_level)?;
+        self.optional_u32(metadata.component)?;
+        self.optional_string(&metadata.series)
+    }
+
+    fn channel_kind(&mut self, kind: ChannelKind) -> Result<()> {
+        match kind {
+            ChannelKind::Imaginary => self.u8(0),
+            ChannelKind::Uncertainty => self.u8(1),
+        }
+    }
+
+    fn problem(&mut self, problem: &ReadProblem) -> Result<()> {
+        match problem {
+            ReadProblem::Unreadable(reason) => {
+                self.u8(0)?;
+                self.string(reason)
+            },
+            ReadProblem::NoData => self.u8(1),
+            ReadProblem::BadTime(value) => {
+                self.u8(2)?;
+                self.string(value)
+            },
+            ReadProblem::TruncatedBlock => self.u8(3),
+            ReadProblem::MalformedLine => self.u8(4),
+            ReadProblem::MissingColumn(column) => {
+                self.u8(5)?;
+                self.string(column)
+            },
+        }
+    }
+}
+
+struct CacheReader<R: Read + Seek> {
+    reader: R,
+}
+
+impl<R: Read + Seek> CacheReader<R> {
+    /// Reads the whole cache, of the given length in bytes. Returns None if it does not match the key
+    /// or settings.
+    fn read_contents(&mut self, key: SourceKey, settings: &LoadSettings, length: u64) -> Result<Option<FileContents>> {
+        let mut magic = vec![0; MAGIC.len()];
+        self.reader.read_exact(&mut magic)?;
+        let cached_key = SourceKey {
+            length: self.u64()?,
+            modified_secs: self.u64()?,
+            modified_nanos: self.u32()?,
+        };
+        if magic != MAGIC || cached_key != key || self.byte_string()? != settings_key(settings)? {
+            return Ok(None);
+        }
+
+        let n_metadata = self.u32()?;
+        let mut metadata_table = Vec::new();
+        for _ in 0..n_metadata {
+            metadata_table.push(self.metadata()?);
+        }
+
+        let n_lines = self.u64()?;
+        let mut line_headers = Vec::new();
+        for _ in 0..n_lines {
+            let time = self.f64()?;
+            let index = self.u32()?;
+            let _offset = self.u64()?; // points are read in order, offsets are for random access
+            let n_points = self.u64()?;
+            let n_channels = self.u32()?;
+            let mut kinds = Vec::new();
+            for _ in 0..n_channels {
+                kinds.push(self.channel_kind()?);
+            }
+            line_headers.push((time, index, n_points, kinds));
+        }
+
+        let n_problems = self.u64()?;
+        let mut problems = LineProblems::new();
+        for _ in 0..n_problems {
+            let line_number = self.u64()? as usize;
+            problems.push((line_number, self.problem()?));
+        }
+
+        // The points must fit into the rest of the file, or the cache is damaged
+        let n_values = line_headers.iter()
+            .try_fold(0u64, |sum, (_, _, n_points, kinds)| n_points.checked_mul(2 + kinds.len() as u64)?.checked_add(sum))
+            .ok_or("cache holds too many points")?;
+        let remaining = length.saturating_sub(self.reader.stream_position()?);
+        if !matches!(n_values.checked_mul(8), Some(size) if size <= remaining) {
+            return Err("cache is shorter than its points".into());
+        }
+
+        let mut time_line_pairs = Vec::new();
+        let mut buffer = Vec::new();
+        for (time, index, n_points, kinds) in line_headers {
+            buffer.resize(n_points as usize * 16, 0);
+            self.reader.read_exact(&mut buffer)?;
+            let points = buffer.chunks_exact(16).map(|chunk| {
+                let (x, y) = chunk.split_at(8);
+                (f64::from_le_bytes(x.try_into().unwrap()), f64::from_le_bytes(y.try_into().unwrap()))
+            }).collect();
+            let mut channels = Vec::new();
+            for kind in kinds {
+                buffer.resize(n_points as usize * 8, 0);
+                self.reader.read_exact(&mut buffer)?;
+                channels.push(Channel {
+                    kind,
+                    values: buffer.chunks_exact(8).map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap())).collect(),
+                });
+            }
+            time_line_pairs.push((time, DataLine {
+                points,
+                channels,
+                metadata: metadata_table.get(index as usize).cloned(),
+            }));
+        }
+
+        Ok(Some((time_line_pairs, problems)))
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut buffer = [0; N];
+        self.reader.read_exact(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.bytes()?))
+    }
+
+    fn byte_string(&mut self) -> Result<Vec<u8>> {
+        let length = self.u64()?;
+        let mut buffer = Vec::new();
+        (&mut self.reader).take(length).read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+
+    fn string(&mut self) -> Result<String> {
+        Ok(String::from_utf8(self.byte_string()?)?)
+    }
+
+    fn optional_string(&mut self) -> Result<Option<String>> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.string()?)),
+        }
+    }
+
+    fn optional_u32(&mut self) -> Result<Option<u32>> {
+        match self.u8()? {
+            0 => Ok(None),
+            _ => Ok(Some(self.u32()?)),
+        }
+    }
+
+    fn metadata(&mut self) -> Result<LineMetadata> {
+        Ok(LineMetadata {
+            axis: self.string()?,
+            variable: self.string()?,
+            rank: self.optional_u32()?,
+            grid: self.optional_u32()?,
+            output_type: self.optional_string()?,
+            orientation: self.optional_string()?,
+            refinement_level: self.optional_u32()?,
+            component: self.optional_u32()?,
+            series: self.optional_string()?,
+        })
+    }
+
+    fn channel_kind(&mut self) -> Result<ChannelKind> {
+        match self.u8()? {
+            0 => Ok(ChannelKind::Imaginary),
+            _ => Ok(ChannelKind::Uncertainty),
+        }
+    }
+
+    fn problem(&mut self) -> Result<ReadProblem> {
+        match self.u8()? {
+            0 => Ok(ReadProblem::Unreadable(self.string()?)),
+            1 => Ok(ReadProblem::NoData),
+            2 => Ok(ReadProblem::BadTime(self.string()?)),
+            3 => Ok(ReadProblem::TruncatedBlock),
+            5 => Ok(ReadProblem::MissingColumn(self.string()?)),
+            _ => Ok(ReadProblem::MalformedLine),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const KEY: SourceKey = SourceKey { length: 1, modified_secs: 2, modified_nanos: 3 };
+
+    fn contents() -> FileContents {
+        let metadata = LineMetadata::from_header("\"Output of variable psi, x axis").unwrap();
+        let mut line = DataLine::new(Some(metadata), &[ChannelKind::Uncertainty]);
+        line.push((0.0, 1.0), &[0.1]);
+        line.push((1.0, 2.0), &[0.2]);
+        let mut headerless = DataLine::new(None, &[]);
+        headerless.push((0.5, -1.0), &[]);
+        (vec![(0.0, line.clone()), (1.0, line), (1.0, headerless)], vec![(7, ReadProblem::MalformedLine)])
+    }
+
+    fn written(settings: &LoadSettings) -> Vec<u8> {
+        let (time_line_pairs, problems) = contents();
+        let mut writer = CacheWriter { writer: Vec::new() };
+        writer.write_contents(KEY, settings, &time_line_pairs, &problems).unwrap();
+        writer.writer
+    }
+
+    fn read(bytes: Vec<u8>, settings: &LoadSettings) -> Result<Option<FileContents>> {
+        let length = bytes.len() as u64;
+        CacheReader { reader: Cursor::new(bytes) }.read_contents(KEY, settings, length)
+    }
+
+    #[test]
+    fn cached_lines_are_read_back() {
+        let settings = LoadSettings::new();
+        let (time_line_pairs, problems) = read(written(&settings), &settings).unwrap().unwrap();
+        let (expected_pairs, expected_problems) = contents();
+        assert!(time_line_pairs == expected_pairs);
+        assert_eq!(problems, expected_problems);
+    }
+
+    #[test]
+    fn only_settings_that_affect_reading_invalidate_the_cache() {
+        let settings = LoadSettings::new();
+        let bytes = written(&settings);
+
+        let mut other = settings.clone();
+        other.merge_restarts = !other.merge_restarts;
+        other.on_demand = !other.on_demand;
+        assert!(read(bytes.clone(), &other).unwrap().is_some());
+
+        let mut other = settings.clone();
+        other.columns.y = vec![Column::Number(3)];
+        assert!(read(bytes.clone(), &other).unwrap().is_none());
+
+        let mut other = settings;
+        other.time_filter.stride = 2;
+        assert!(read(bytes, &other).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_cache_is_not_read() {
+        let settings = LoadSettings::new();
+        let mut bytes = written(&settings);
+        bytes.truncate(bytes.len() - 8);
+        assert!(read(bytes, &settings).is_err());
+    }
+}
@@ -0,0 +1,438 @@
+use crate::compression::strip_compression_extension;
+use crate::data::{mark_truncated, parse_time, ChannelKind, Column, ColumnSelection, DataLine, LineMetadata, LineProblems, ReadProblem, StepSelector, Time};
+use crate::lazy::{lines_with_offsets, BlockIndex};
+use crate::plane::{Patch, PlaneMetadata};
+use std::io::BufRead;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Column names of a Carpet ASCII file, as announced in its comment header, e.g.
+/// `# column format: 1:it 2:tl 3:rl 4:c 5:ml 6:ix 7:iy 8:iz 9:time 10:x 11:y 12:z 13:data`
+/// and `# data columns: 13:rho`
+struct ColumnLayout {
+    names: Vec<(usize, String)>,
+    data_columns: Vec<usize>,
+}
+
+impl ColumnLayout {
+    /// The layout used by Carpet if the file does not say otherwise
+    fn new() -> ColumnLayout {
+        let names = ["it", "tl", "rl", "c", "ml", "ix", "iy", "iz", "time", "x", "y", "z", "data"];
+        ColumnLayout {
+            names: names.iter().enumerate().map(|(i, n)| (i+1, n.to_string())).collect(),
+            data_columns: vec![13],
+        }
+    }
+
+    /// Parses a list like "1:it 2:tl 3:rl" into column numbers and names
+    fn parse_column_list(list: &str) -> Vec<(usize, String)> {
+        list.split_whitespace()
+            .filter_map(|entry| entry.split_once(':'))
+            .filter_map(|(index, name)| index.parse().ok().map(|i| (i, name.to_string())))
+            .collect()
+    }
+
+    /// Updates the layout from a comment line. Returns true if the line described columns.
+    fn update_from_comment(&mut self, comment: &str) -> bool {
+        let comment = comment.trim_start_matches('#').trim();
+        if let Some(list) = comment.strip_prefix("column format:") {
+            self.names = ColumnLayout::parse_column_list(list);
+            true
+        } else if let Some(list) = comment.strip_prefix("data columns:") {
+            let data_columns = ColumnLayout::parse_column_list(list);
+            self.data_columns = data_columns.iter().map(|(i, _)| *i).collect();
+            for (index, name) in data_columns {
+                match self.names.iter_mut().find(|(i, _)| *i == index) {
+                    Some(entry) => entry.1 = name,
+                    None => self.names.push((index, name)),
+                }
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Picks the columns to read, falling back to Carpet's defaults where the header is silent
+    fn resolve(&self, columns: &ColumnSelection, axis: Option<&str>) -> ResolvedColumns {
+        // With the position of each y column among the selected ones, which its channels go by
+        let y = match columns.y.iter().enumerate()
+            .filter_map(|(position, c)| self.number_of(c).map(|number| (position, number)))
+            .collect::<Vec<(usize, usize)>>() {
+            y if !y.is_empty() => y,
+            _ => vec![(0, self.data_columns.first().copied().unwrap_or(13))],
+        };
+        ResolvedColumns {
+            x: columns.x.as_ref().and_then(|c| self.number_of(c))
+                .or_else(|| axis.and_then(|a| self.index_of(a)))
+                .unwrap_or(10),
+            channels: y.iter().map(|(position, _)| {
+                columns.channels_of_y(*position).into_iter()
+                    .filter_map(|(kind, column)| self.number_of(column).map(|number| (kind, number)))
+                    .collect()
+            }).collect(),
+            y: y.iter().map(|(_, number)| *number).collect(),
+            time: columns.time.as_ref().and_then(|c| self.number_of(c))
+                .or_else(|| self.index_of("time"))
+                .unwrap_or(9),
+            keys: ["it", "tl", "rl", "c", "ml"].iter()
+                .filter_map(|name| self.index_of(name))
+                .collect(),
+        }
+    }
+
+    fn number_of(&self, column: &Column) -> Option<usize> {
+        match column {
+            Column::Number(number) => Some(*number),
+            Column::Name(name) => self.index_of(name),
+        }
+    }
+
+    fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().find(|(_, n)| n == name).map(|(i, _)| *i)
+    }
+
+    fn name_of(&self, index: usize) -> String {
+        match self.names.iter().find(|(i, _)| *i == index) {
+            Some((_, name)) => name.clone(),
+            None => format!("column {}", index),
+        }
+    }
+}
+
+/// Column numbers used while reading rows
+struct ResolvedColumns {
+    x: usize,
+    y: Vec<usize>, // each making its own dataline
+    channels: Vec<Vec<(ChannelKind, usize)>>, // of each y column
+    time: usize,
+    keys: Vec<usize>, // a change in any of these begins a new dataline
+}
+
+/// Guesses the coordinate axis of a Carpet 1D file from its name, e.g. "x" for "rho.x.asc"
+pub fn axis_from_filename(filename: &str) -> Option<&str> {
+    let stem = strip_compression_extension(filename).strip_suffix(".asc")?;
+    let (_, axis) = stem.rsplit_once('.')?;
+    match axis {
+        "x" | "y" | "z" => Some(axis),
+        _ => None,
+    }
+}
+
+/// Guesses the plane of a Carpet 2D file from its name, e.g. ("x", "y") for "rho.xy.asc"
+pub fn plane_from_filename(filename: &str) -> Option<(&str, &str)> {
+    let stem = strip_compression_extension(filename).strip_suffix(".asc")?;
+    let (_, plane) = stem.rsplit_once('.')?;
+    match plane {
+        "xy" | "xz" | "yz" => Some(plane.split_at(1)),
+        _ => None,
+    }
+}
+
+/// Reads the lines of a Carpet 1D ASCII file. Rows are grouped into one dataline for each
+/// iteration, refinement level and component, and y column. Unless selected otherwise, x is the
+/// coordinate along the given axis and y is the first data column.
+pub fn read_carpet_datalines<R: BufRead>(reader: R, columns: &ColumnSelection, axis: Option<&str>,
+                                         selector: &mut StepSelector, problems: &mut LineProblems) -> Result<Vec<(Time, DataLine)>> {
+    let mut layout = ColumnLayout::new();
+    let mut resolved = layout.resolve(columns, axis);
+
+    let mut time_line_pairs: Vec<(Time, DataLine)> = Vec::new();
+    let mut current_key: Vec<String> = Vec::new();
+    let mut line_number = 0;
+
+    for l in reader.lines() {
+        let line = l?;
+        line_number += 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            if layout.update_from_comment(line) {
+                resolved = layout.resolve(columns, axis);
+            }
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let word = |column: usize| column.checked_sub(1).and_then(|i| words.get(i)).copied();
+
+        // skip malformed lines, e.g. caused by a program being stopped mid-write
+        let parse = |column: usize| word(column).and_then(|w| w.parse::<f64>().ok());
+        let ys = resolved.y.iter().map(|c| parse(*c)).collect::<Option<Vec<f64>>>();
+        let channel_values = resolved.channels.iter()
+            .map(|channels| channels.iter().map(|(_, c)| parse(*c)).collect::<Option<Vec<f64>>>())
+            .collect::<Option<Vec<Vec<f64>>>>();
+        let time = word(resolved.time).and_then(parse_time);
+        let (x, ys, channel_values, time) = match (parse(resolved.x), ys, channel_values, time) {
+            (Some(x), Some(ys), Some(channel_values), Some(t)) => (x, ys, channel_values, t),
+            _ => {
+                problems.push((line_number, ReadProblem::MalformedLine));
+                continue;
+            },
+        };
+        if x.is_nan() || !selector.keeps(time) {
+            continue;
+        }
+
+        // Begin a new dataline whenever iteration, refinement level or component change
+        let key_changed = !resolved.keys.iter().map(|c| word(*c))
+            .eq(current_key.iter().map(|k| Some(k.as_str())));
+        if key_changed || time_line_pairs.is_empty() {
+            let parse_column = |name: &str| layout.index_of(name).and_then(word).and_then(|w| w.parse().ok());
+            for (y, channels) in resolved.y.iter().zip(&resolved.channels) {
+                let metadata = LineMetadata {
+                    axis: layout.name_of(resolved.x),
+                    variable: layout.name_of(*y),
+                    rank: None,
+                    grid: None,
+                    output_type: None,
+                    orientation: None,
+                    refinement_level: parse_column("rl"),
+                    component: parse_column("c"),
+                    series: None,
+                };
+                let kinds: Vec<ChannelKind> = channels.iter().map(|(kind, _)| *kind).collect();
+                time_line_pairs.push((time, DataLine::new(Some(metadata), &kinds)));
+            }
+            current_key = resolved.keys.iter().filter_map(|c| word(*c)).map(|w| w.to_string()).collect();
+        }
+
+        // Lines of the current key are the last ones added, in the order of the y columns
+        let first_line = time_line_pairs.len() - ys.len();
+        for (((_, dataline), y), values) in time_line_pairs[first_line..].iter_mut().zip(ys).zip(channel_values) {
+            if !y.is_nan() && !values.iter().any(|v| v.is_nan()) {
+                dataline.push((x, y), &values);
+            }
+        }
+    }
+    mark_truncated(problems, line_number);
+
+    Ok(time_line_pairs)
+}
+
+/// Reads the patches of a Carpet 2D ASCII file, one for each iteration, refinement level and
+/// component. Values are read from the first y column, or the first data column unless selected
+/// otherwise, and placed at the coordinates along the axes of the given plane.
+pub fn read_carpet_patches<R: BufRead>(reader: R, columns: &ColumnSelection, plane: (&str, &str),
+                                       selector: &mut StepSelector, problems: &mut LineProblems) -> Result<(PlaneMetadata, Vec<(Time, Patch)>)> {
+    let mut layout = ColumnLayout::new();
+    let mut resolved = layout.resolve(columns, Some(plane.0));
+    let mut second_axis = layout.index_of(plane.1).unwrap_or(11);
+
+    let mut time_patch_pairs: Vec<(Time, Patch)> = Vec::new();
+    let mut points: Vec<(f64, f64, f64)> = Vec::new(); // of the current patch
+    let mut current: Option<(Time, Option<u32>)> = None;
+    let mut current_key: Vec<String> = Vec::new();
+    let mut line_number = 0;
+
+    for l in reader.lines() {
+        let line = l?;
+        line_number += 1;
+        let line = line.trim();
+        // Blank lines only separate rows of the grid
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            if layout.update_from_comment(line) {
+                resolved = layout.resolve(columns, Some(plane.0));
+                second_axis = layout.index_of(plane.1).unwrap_or(11);
+            }
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let word = |column: usize| column.checked_sub(1).and_then(|i| words.get(i)).copied();
+
+        // skip malformed lines, e.g. caused by a program being stopped mid-write
+        let parse = |column: usize| word(column).and_then(|w| w.parse::<f64>().ok());
+        let time = word(resolved.time).and_then(parse_time);
+        let (x, y, value, time) = match (parse(resolved.x), parse(second_axis), parse(resolved.y[0]), time) {
+            (Some(x), Some(y), Some(value), Some(t)) => (x, y, value, t),
+            _ => {
+                problems.push((line_number, ReadProblem::MalformedLine));
+                continue;
+            },
+        };
+        if x.is_nan() || y.is_nan() || !selector.keeps(time) {
+            continue;
+        }
+
+        // Begin a new patch whenever iteration, refinement level or component change
+        let key_changed = !resolved.keys.iter().map(|c| word(*c))
+            .eq(current_key.iter().map(|k| Some(k.as_str())));
+        if key_changed || current.is_none() {
+            if let Some((time, refinement_level)) = current {
+                time_patch_pairs.push((time, Patch::from_points(&points, refinement_level)));
+            }
+            points.clear();
+            let refinement_level = layout.index_of("rl").and_then(word).and_then(|w| w.parse().ok());
+            current = Some((time, refinement_level));
+            current_key = resolved.keys.iter().filter_map(|c| word(*c)).map(|w| w.to_string()).collect();
+        }
+        points.push((x, y, value));
+    }
+    if let Some((time, refinement_level)) = current {
+        time_patch_pairs.push((time, Patch::from_points(&points, refinement_level)));
+    }
+    mark_truncated(problems, line_number);
+
+    let metadata = PlaneMetadata {
+        axes: (layout.name_of(resolved.x), layout.name_of(second_axis)),
+        variable: layout.name_of(resolved.y[0]),
+    };
+    Ok((metadata, time_patch_pairs))
+}
+
+/// Finds the blocks of rows of a Carpet 1D ASCII file that share the same time. Only the first y
+/// column is named, since files are only indexed when a single one is read.
+pub fn index_carpet_blocks<R: BufRead>(reader: R, columns: &ColumnSelection, axis: Option<&str>, problems: &mut LineProblems) -> Result<Vec<BlockIndex>> {
+    let mut layout = ColumnLayout::new();
+    let mut resolved = layout.resolve(columns, axis);
+
+    let mut blocks: Vec<BlockIndex> = Vec::new();
+    let mut line_number = 0;
+
+    for l in lines_with_offsets(reader) {
+        let (offset, line) = l?;
+        line_number += 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            if layout.update_from_comment(line) {
+                resolved = layout.resolve(columns, axis);
+            }
+            continue;
+        }
+
+        let word = resolved.time.checked_sub(1).and_then(|i| line.split_whitespace().nth(i));
+        let time = match word.and_then(parse_time) {
+            Some(time) => time,
+            _ => {
+                problems.push((line_number, ReadProblem::MalformedLine));
+                continue;
+            },
+        };
+        if blocks.last().map(|b| b.time) != Some(time) {
+            // Only for naming the series, refinement level and component are read with the rows
+            let metadata = LineMetadata {
+                axis: layout.name_of(resolved.x),
+                variable: layout.name_of(resolved.y[0]),
+                rank: None,
+                grid: None,
+                output_type: None,
+                orientation: None,
+                refinement_level: None,
+                component: None,
+                series: None,
+            };
+            blocks.push(BlockIndex {
+                time,
+                start: offset,
+                metadata: Some(metadata),
+            });
+        }
+    }
+    mark_truncated(problems, line_number);
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::LoadSettings;
+
+    const HEADER: &str = "# 1D ASCII output created by CarpetIOASCII\n\
+                          # column format: 1:it 2:tl 3:rl 4:c 5:ml 6:ix 7:iy 8:iz 9:time 10:x 11:y 12:z 13:data\n\
+                          # data columns: 13:rho 14:press\n";
+
+    fn read(text: &str, columns: &ColumnSelection, axis: Option<&str>) -> (Vec<(Time, DataLine)>, LineProblems) {
+        let mut problems = LineProblems::new();
+        let time_line_pairs = read_carpet_datalines(text.as_bytes(), columns, axis, &mut StepSelector::all(), &mut problems).unwrap();
+        (time_line_pairs, problems)
+    }
+
+    #[test]
+    fn data_columns_of_the_header_name_the_columns() {
+        let mut layout = ColumnLayout::new();
+        for comment in HEADER.lines() {
+            layout.update_from_comment(comment);
+        }
+        assert_eq!(layout.data_columns, vec![13, 14]);
+        assert_eq!(layout.name_of(13), "rho");
+        assert_eq!(layout.name_of(14), "press");
+        assert_eq!(layout.name_of(15), "column 15");
+        assert_eq!(layout.index_of("time"), Some(9));
+
+        let mut columns = LoadSettings::new().columns;
+        columns.y = vec![Column::Name("press".to_string())];
+        columns.uncertainty = vec![Column::Number(13)];
+        let resolved = layout.resolve(&columns, Some("y"));
+        assert_eq!((resolved.x, resolved.y, resolved.time), (11, vec![14], 9));
+        assert_eq!(resolved.channels, vec![vec![(ChannelKind::Uncertainty, 13)]]);
+    }
+
+    #[test]
+    fn rows_are_read_with_the_columns_of_the_header() {
+        let text = format!("{}0 0 0 0 0 0 0 0 0.5 -1 0 0 1 2\n0 0 0 0 0 1 0 0 0.5 1 0 0 3 4\n\n\
+                            0 0 1 0 0 0 0 0 0.5 0 0 0 5 6\n", HEADER);
+        let (pairs, problems) = read(&text, &LoadSettings::new().columns, Some("x"));
+        assert!(problems.is_empty());
+        // A line for each refinement level
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(pairs[0].0, 0.5);
+        let metadata = pairs[0].1.metadata.as_ref().unwrap();
+        assert_eq!((metadata.variable.as_str(), metadata.axis.as_str()), ("rho", "x"));
+        assert_eq!(pairs[0].1.points, vec![(-1.0, 1.0), (1.0, 3.0)]);
+        assert_eq!(pairs[1].1.metadata.as_ref().unwrap().refinement_level, Some(1));
+        assert_eq!(pairs[1].1.points, vec![(0.0, 5.0)]);
+    }
+
+    #[test]
+    fn files_without_a_header_have_the_default_layout() {
+        let (pairs, problems) = read("7 0 0 0 0 0 0 0 2.0 0.25 0 0 9\n7 0 0 0 0 0 0 0 2.0\n", &LoadSettings::new().columns, Some("x"));
+        assert_eq!(problems, vec![(2, ReadProblem::TruncatedBlock)]);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].0, 2.0);
+        assert_eq!(pairs[0].1.metadata.as_ref().unwrap().variable, "data");
+        assert_eq!(pairs[0].1.points, vec![(0.25, 9.0)]);
+    }
+
+    #[test]
+    fn rows_without_a_finite_time_are_malformed() {
+        let text = "0 0 0 0 0 0 0 0 nan 0.25 0 0 9\n0 0 0 0 0 0 0 0 1.0 0.25 0 0 8\n";
+        let (pairs, problems) = read(text, &LoadSettings::new().columns, Some("x"));
+        assert_eq!(problems, vec![(1, ReadProblem::MalformedLine)]);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].0, pairs[0].1.points.clone()), (1.0, vec![(0.25, 8.0)]));
+    }
+
+    #[test]
+    fn points_with_missing_channel_values_are_left_out() {
+        let mut columns = LoadSettings::new().columns;
+        columns.uncertainty = vec![Column::Name("press".to_string())];
+        let text = format!("{}0 0 0 0 0 0 0 0 0 0 0 0 1 nan\n0 0 0 0 0 1 0 0 0 1 0 0 2 0.5\n", HEADER);
+        let (pairs, _) = read(&text, &columns, Some("x"));
+        assert_eq!(pairs[0].1.points, vec![(1.0, 2.0)]);
+        assert_eq!(pairs[0].1.channel(ChannelKind::Uncertainty), Some(&[0.5][..]));
+    }
+
+    #[test]
+    fn channels_go_with_their_y_column_when_another_is_missing() {
+        let mut layout = ColumnLayout::new();
+        for comment in HEADER.lines() {
+            layout.update_from_comment(comment);
+        }
+        let mut columns = LoadSettings::new().columns;
+        columns.y = vec![Column::Name("vel".to_string()), Column::Name("rho".to_string())];
+        columns.uncertainty = vec![Column::Number(15), Column::Name("press".to_string())];
+        let resolved = layout.resolve(&columns, Some("x"));
+        assert_eq!(resolved.y, vec![13]);
+        assert_eq!(resolved.channels, vec![vec![(ChannelKind::Uncertainty, 14)]]);
+    }
+}
@@ -0,0 +1,137 @@
+use crate::data::{Channel, DataLine, DataSlice, Point};
+
+/// Merges the lines of a slice into a single curve, e.g. the refinement levels and components of a
+/// mesh-refined run. Where lines overlap, the line of the finest refinement level wins. Among lines
+/// of the same level, the first one wins, which removes the duplicate points of ghost and buffer
+/// zones. Lines without a refinement level count as the coarsest. Channels are kept if all lines
+/// carry the same kinds of them.
+pub fn composite(slice: &DataSlice) -> DataSlice {
+    let mut lines: Vec<&DataLine> = slice.datalines.iter().filter(|l| !l.points.is_empty()).collect();
+    // Stable, so lines of the same level stay in order
+    lines.sort_by_key(|l| std::cmp::Reverse(refinement_level(l)));
+
+    let mut covered: Vec<(f64, f64)> = Vec::new(); // x ranges of the lines taken so far
+    let mut taken: Vec<(usize, usize)> = Vec::new(); // line and point indices
+    for (i, line) in lines.iter().enumerate() {
+        taken.extend(line.points.iter().enumerate()
+            .filter(|(_, (x, _))| !covered.iter().any(|range| contains(range, *x)))
+            .map(|(j, _)| (i, j)));
+        covered.push(x_range(&line.points));
+    }
+    let x_of = |(i, j): &(usize, usize)| lines[*i].points[*j].0;
+    taken.sort_by(|a, b| x_of(a).total_cmp(&x_of(b)));
+    taken.dedup_by(|a, b| same_x(x_of(a), x_of(b)));
+
+    let same_channels = lines.windows(2).all(|pair| {
+        pair[0].channels.iter().map(|c| c.kind).eq(pair[1].channels.iter().map(|c| c.kind))
+    });
+    let channels = match lines.first() {
+        Some(first) if same_channels => first.channels.iter().enumerate().map(|(k, channel)| Channel {
+            kind: channel.kind,
+            values: taken.iter().map(|(i, j)| lines[*i].channels[k].values[*j]).collect(),
+        }).collect(),
+        _ => Vec::new(),
+    };
+
+    let metadata = lines.first().and_then(|l| l.metadata.clone()).map(|mut metadata| {
+        metadata.refinement_level = None;
+        metadata.component = None;
+        metadata
+    });
+    DataSlice {
+        time: slice.time,
+        datalines: vec![DataLine {
+            points: taken.iter().map(|(i, j)| lines[*i].points[*j]).collect(),
+            channels,
+            metadata,
+        }],
+    }
+}
+
+fn refinement_level(line: &DataLine) -> Option<u32> {
+    line.metadata.as_ref().and_then(|m| m.refinement_level)
+}
+
+fn x_range(points: &[Point]) -> (f64, f64) {
+    points.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), (x, _)| (min.min(*x), max.max(*x)))
+}
+
+/// Whether a range contains x, counting its ends, which may be printed with different rounding
+fn contains((min, max): &(f64, f64), x: f64) -> bool {
+    (x >= *min && x <= *max) || same_x(x, *min) || same_x(x, *max)
+}
+
+/// Coordinates of the same grid point, printed by different components or levels
+fn same_x(x1: f64, x2: f64) -> bool {
+    (x1 - x2).abs() <= 1e-12 * x1.abs().max(x2.abs()).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::LineMetadata;
+
+    /// A line of a refinement level, with y telling the level apart
+    fn line(level: Option<u32>, xs: &[f64]) -> DataLine {
+        let metadata = LineMetadata::from_header("\"Output of variable rho, x axis").map(|mut metadata| {
+            metadata.refinement_level = level;
+            metadata
+        });
+        let mut line = DataLine::new(metadata, &[]);
+        for x in xs {
+            line.push((*x, level.map_or(-1.0, f64::from)), &[]);
+        }
+        line
+    }
+
+    fn composite_of(datalines: Vec<DataLine>) -> Vec<Point> {
+        let slice = composite(&DataSlice { time: 0.0, datalines });
+        assert_eq!(slice.datalines.len(), 1);
+        slice.datalines[0].points.clone()
+    }
+
+    #[test]
+    fn points_without_a_number_for_x_do_not_panic() {
+        let points = composite_of(vec![line(Some(0), &[1.0, f64::NAN, 0.0])]);
+        assert_eq!(points[..2], [(0.0, 0.0), (1.0, 0.0)]);
+    }
+
+    #[test]
+    fn finer_levels_win_where_lines_overlap() {
+        let coarse = line(Some(0), &[0.0, 1.0, 2.0, 3.0, 4.0]);
+        let fine = line(Some(1), &[1.0, 1.5, 2.0, 2.5]);
+        let points = composite_of(vec![coarse, fine]);
+        assert_eq!(points, vec![(0.0, 0.0), (1.0, 1.0), (1.5, 1.0), (2.0, 1.0), (2.5, 1.0), (3.0, 0.0), (4.0, 0.0)]);
+    }
+
+    #[test]
+    fn components_of_a_level_are_joined_without_duplicates() {
+        // Ghost zones repeat the points next to the other component, with different rounding
+        let left = line(Some(0), &[0.0, 1.0, 2.0]);
+        let right = line(Some(0), &[2.0 + 1e-14, 3.0, 4.0]);
+        let points = composite_of(vec![right, left]);
+        assert_eq!(points.iter().map(|(x, _)| x.round()).collect::<Vec<f64>>(), vec![0.0, 1.0, 2.0, 3.0, 4.0]);
+    }
+
+    #[test]
+    fn lines_without_a_level_count_as_the_coarsest() {
+        let points = composite_of(vec![line(None, &[0.0, 1.0, 2.0]), line(Some(0), &[1.0, 2.0])]);
+        assert_eq!(points, vec![(0.0, -1.0), (1.0, 0.0), (2.0, 0.0)]);
+    }
+
+    #[test]
+    fn channels_are_kept_only_if_all_lines_have_them() {
+        use crate::data::ChannelKind;
+
+        let with_uncertainty = |level, xs: &[f64]| {
+            let mut line = line(Some(level), xs);
+            line.channels = vec![Channel { kind: ChannelKind::Uncertainty, values: vec![0.1 * f64::from(level + 1); xs.len()] }];
+            line
+        };
+        let slice = composite(&DataSlice { time: 0.0, datalines: vec![with_uncertainty(0, &[0.0, 2.0]), with_uncertainty(1, &[1.0])] });
+        assert_eq!(slice.datalines[0].channel(ChannelKind::Uncertainty), Some(&[0.1, 0.2, 0.1][..]));
+
+        let slice = composite(&DataSlice { time: 0.0, datalines: vec![with_uncertainty(0, &[0.0, 2.0]), line(Some(1), &[1.0])] });
+        assert!(slice.datalines[0].channels.is_empty());
+    }
+}
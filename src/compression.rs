@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Compression formats that are decompressed transparently when reading files
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+impl Compression {
+    /// Detects the compression of a file by the magic bytes at its start
+    pub fn detect(start: &[u8]) -> Compression {
+        if start.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if start.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if start.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// Removes the extension of a compressed file, e.g. "rho.xg" from "rho.xg.gz"
+pub fn strip_compression_extension(filename: &str) -> &str {
+    for extension in &[".gz", ".bz2", ".xz"] {
+        if let Some(stripped) = filename.strip_suffix(extension) {
+            return stripped;
+        }
+    }
+    filename
+}
+
+/// Opens a file for reading, decompressing it on the fly if it is compressed
+pub fn open_file(filename: &str) -> Result<(Compression, Box<dyn BufRead>)> {
+    decompress(File::open(filename)?)
+}
+
+/// Wraps a reader, decompressing what it reads if that is compressed
+pub fn decompress<R: Read + 'static>(reader: R) -> Result<(Compression, Box<dyn BufRead>)> {
+    use bzip2::read::MultiBzDecoder;
+    use flate2::read::MultiGzDecoder;
+    use xz2::read::XzDecoder;
+
+    let mut reader = BufReader::new(reader);
+    let compression = Compression::detect(reader.fill_buf()?);
+
+    let reader: Box<dyn BufRead> = match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(BufReader::new(MultiGzDecoder::new(reader))),
+        Compression::Bzip2 => Box::new(BufReader::new(MultiBzDecoder::new(reader))),
+        Compression::Xz => Box::new(BufReader::new(XzDecoder::new_multi_decoder(reader))),
+    };
+
+    Ok((compression, reader))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+
+    const TEXT: &str = "\"Time = 0\n0 1\n1 2\n";
+
+    /// Compresses the text with the encoder of the given format
+    fn compress(compression: Compression) -> Vec<u8> {
+        match compression {
+            Compression::None => TEXT.as_bytes().to_vec(),
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(TEXT.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            },
+            Compression::Bzip2 => {
+                let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+                encoder.write_all(TEXT.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            },
+            Compression::Xz => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(TEXT.as_bytes()).unwrap();
+                encoder.finish().unwrap()
+            },
+        }
+    }
+
+    #[test]
+    fn compression_is_detected_by_magic_bytes() {
+        assert_eq!(Compression::detect(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(Compression::detect(b"BZh91AY&SY"), Compression::Bzip2);
+        assert_eq!(Compression::detect(&[0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00]), Compression::Xz);
+        assert_eq!(Compression::detect(TEXT.as_bytes()), Compression::None);
+        // Too short to tell
+        assert_eq!(Compression::detect(&[0x1f]), Compression::None);
+        assert_eq!(Compression::detect(&[]), Compression::None);
+    }
+
+    #[test]
+    fn compression_extensions_are_stripped() {
+        assert_eq!(strip_compression_extension("rho.xg.gz"), "rho.xg");
+        assert_eq!(strip_compression_extension("rho.x.asc.bz2"), "rho.x.asc");
+        assert_eq!(strip_compression_extension("data.csv.xz"), "data.csv");
+        assert_eq!(strip_compression_extension("rho.xg"), "rho.xg");
+        // Only the last extension is a compression one
+        assert_eq!(strip_compression_extension("rho.gz.xg"), "rho.gz.xg");
+    }
+
+    #[test]
+    fn compressed_text_is_read_back() {
+        for &compression in &[Compression::None, Compression::Gzip, Compression::Bzip2, Compression::Xz] {
+            let (detected, mut reader) = decompress(Cursor::new(compress(compression))).unwrap();
+            assert_eq!(detected, compression);
+            let mut text = String::new();
+            reader.read_to_string(&mut text).unwrap();
+            assert_eq!(text, TEXT);
+        }
+    }
+
+    #[test]
+    fn plain_file_is_read_as_it_is() {
+        let path = std::env::temp_dir().join(format!("muninn-plain-{}.xg", std::process::id()));
+        std::fs::write(&path, TEXT).unwrap();
+        let (compression, reader) = open_file(&path.display().to_string()).unwrap();
+        let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(compression, Compression::None);
+        assert_eq!(lines, vec!["\"Time = 0", "0 1", "1 2"]);
+    }
+}
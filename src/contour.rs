@@ -0,0 +1,229 @@
+use crate::data::Point;
+use crate::plane::Patch;
+use crate::plotting::Range;
+use std::collections::HashMap;
+
+/// How contour levels are spread between the lowest and the highest one
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContourSpacing {
+    Linear,
+    Logarithmic,
+}
+
+/// The values at which contour lines are drawn
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContourLevels {
+    Given(Vec<f64>),
+    // Without a range, the levels divide the values in view into equal parts
+    Spaced { count: usize, spacing: ContourSpacing, range: Option<Range> },
+}
+
+impl ContourLevels {
+    /// Reads levels from a list of values, e.g. "0.1, 0.5, 1", or from "lin N" or "log N" for N
+    /// evenly or logarithmically spaced levels, optionally followed by the lowest and highest
+    /// level, e.g. "log 7 1e-6 1". Returns None for anything else, including empty text.
+    pub fn parse(text: &str) -> Option<ContourLevels> {
+        let words: Vec<&str> = text.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|w| !w.is_empty())
+            .collect();
+        let spacing = match words.first().map(|w| w.to_lowercase()).as_deref() {
+            Some("lin") => ContourSpacing::Linear,
+            Some("log") => ContourSpacing::Logarithmic,
+            Some(_) => {
+                let values = words.iter().map(|w| w.parse::<f64>().ok()).collect::<Option<Vec<f64>>>()?;
+                return Some(ContourLevels::Given(values));
+            },
+            None => return None,
+        };
+
+        let count = words.get(1)?.parse::<usize>().ok().filter(|n| *n > 0)?;
+        let range = match &words[2..] {
+            [] => None,
+            [low, high] => Some((low.parse::<f64>().ok()?, high.parse::<f64>().ok()?)),
+            _ => return None,
+        };
+        let non_positive = matches!(range, Some((low, high)) if low <= 0.0 || high <= 0.0);
+        if spacing == ContourSpacing::Logarithmic && non_positive {
+            return None;
+        }
+        Some(ContourLevels::Spaced { count, spacing, range })
+    }
+
+    /// The levels to draw, given the values in view
+    pub fn values(&self, values_in_view: &[f64]) -> Vec<f64> {
+        let (count, spacing, range) = match self {
+            ContourLevels::Given(levels) => return levels.clone(),
+            ContourLevels::Spaced { count, spacing, range } => (*count, *spacing, *range),
+        };
+        let logarithmic = spacing == ContourSpacing::Logarithmic;
+
+        // Levels at both ends of a given range, or strictly between the extremes of the values
+        let (low, high, steps, first) = match range {
+            Some((low, high)) => (low, high, count.saturating_sub(1).max(1), 0),
+            None => {
+                let extremes = values_in_view.iter()
+                    .filter(|v| v.is_finite() && (!logarithmic || **v > 0.0))
+                    .fold(None, |range: Option<Range>, v| Some(range.map_or((*v, *v), |(a, b)| (a.min(*v), b.max(*v)))));
+                match extremes {
+                    Some((low, high)) => (low, high, count + 1, 1),
+                    None => return Vec::new(),
+                }
+            },
+        };
+        (first..first + count).map(|k| {
+            let f = k as f64 / steps as f64;
+            if logarithmic {
+                10.0_f64.powf(low.log10() + f * (high.log10() - low.log10()))
+            } else {
+                low + f * (high - low)
+            }
+        }).collect()
+    }
+}
+
+/// A grid edge crossed by a contour: the index of its lower left point, and whether it runs along y
+type Edge = (usize, usize, bool);
+
+/// Traces the lines where the values of a patch equal a level, by marching squares over its grid
+/// cells. Cells with a missing value, and cells lying inside one of the hidden regions (e.g. the
+/// extents of finer patches), are skipped.
+pub fn contour_lines(patch: &Patch, level: f64, hidden: &[(Range, Range)]) -> Vec<Vec<Point>> {
+    let (nx, ny) = (patch.xs.len(), patch.ys.len());
+    let is_hidden = |i: usize, j: usize| hidden.iter().any(|((xmin, xmax), (ymin, ymax))| {
+        patch.xs[i] >= *xmin && patch.xs[i+1] <= *xmax && patch.ys[j] >= *ymin && patch.ys[j+1] <= *ymax
+    });
+
+    let mut segments: Vec<(Edge, Edge)> = Vec::new();
+    for j in 0..ny.saturating_sub(1) {
+        for i in 0..nx.saturating_sub(1) {
+            // Counterclockwise from the lower left
+            let corners = [patch.value(i, j), patch.value(i+1, j), patch.value(i+1, j+1), patch.value(i, j+1)];
+            if corners.iter().any(|v| v.is_nan()) || is_hidden(i, j) {
+                continue;
+            }
+            let above: Vec<bool> = corners.iter().map(|v| *v >= level).collect();
+            // Edges leaving each corner counterclockwise: bottom, right, top, left
+            let edges = [(i, j, false), (i+1, j, true), (i, j+1, false), (i, j, true)];
+            let crossed: Vec<Edge> = (0..4).filter(|k| above[*k] != above[(k + 1) % 4]).map(|k| edges[k]).collect();
+            match crossed.len() {
+                2 => segments.push((crossed[0], crossed[1])),
+                4 => {
+                    // A saddle: the value at the center decides which corners are joined
+                    let center = corners.iter().sum::<f64>() / 4.0;
+                    if (center >= level) == above[0] {
+                        // Lines cut off the lower right and upper left corners
+                        segments.push((edges[0], edges[1]));
+                        segments.push((edges[2], edges[3]));
+                    } else {
+                        segments.push((edges[3], edges[0]));
+                        segments.push((edges[1], edges[2]));
+                    }
+                },
+                _ => {},
+            }
+        }
+    }
+
+    // Where the level lies along each crossed edge
+    let crossing = |(i, j, along_y): Edge| {
+        let (i2, j2) = if along_y { (i, j + 1) } else { (i + 1, j) };
+        let (v1, v2) = (patch.value(i, j), patch.value(i2, j2));
+        let f = if v1 == v2 { 0.5 } else { (level - v1) / (v2 - v1) };
+        (patch.xs[i] + f * (patch.xs[i2] - patch.xs[i]), patch.ys[j] + f * (patch.ys[j2] - patch.ys[j]))
+    };
+
+    // Neighbouring cells share an edge, so segments are chained through their edges
+    let mut at_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (k, (a, b)) in segments.iter().enumerate() {
+        at_edge.entry(*a).or_default().push(k);
+        at_edge.entry(*b).or_default().push(k);
+    }
+    let mut used = vec![false; segments.len()];
+    let next_segment = |edge: Edge, used: &mut Vec<bool>| {
+        let k = *at_edge.get(&edge)?.iter().find(|k| !used[**k])?;
+        used[k] = true;
+        let (a, b) = segments[k];
+        Some(if a == edge { b } else { a })
+    };
+
+    let mut lines = Vec::new();
+    for k in 0..segments.len() {
+        if used[k] {
+            continue;
+        }
+        used[k] = true;
+        let (start, end) = segments[k];
+        let mut forward = vec![start, end];
+        while let Some(edge) = next_segment(*forward.last().unwrap(), &mut used) {
+            forward.push(edge);
+        }
+        let mut backward = Vec::new();
+        let mut edge = start;
+        while let Some(previous) = next_segment(edge, &mut used) {
+            backward.push(previous);
+            edge = previous;
+        }
+        lines.push(backward.into_iter().rev().chain(forward).map(crossing).collect());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A patch of the values of a function on an evenly spaced grid over [-1, 1]²
+    fn patch(n: usize, f: impl Fn(f64, f64) -> f64) -> Patch {
+        let coordinates: Vec<f64> = (0..n).map(|i| -1.0 + 2.0 * i as f64 / (n - 1) as f64).collect();
+        let points: Vec<(f64, f64, f64)> = coordinates.iter()
+            .flat_map(|y| coordinates.iter().map(move |x| (*x, *y)))
+            .map(|(x, y)| (x, y, f(x, y)))
+            .collect();
+        Patch::from_points(&points, None)
+    }
+
+    #[test]
+    fn levels_are_parsed_from_lists_and_spacings() {
+        assert_eq!(ContourLevels::parse("0.1, 0.5 1"), Some(ContourLevels::Given(vec![0.1, 0.5, 1.0])));
+        assert_eq!(ContourLevels::parse("log 3 1e-2 1"),
+                   Some(ContourLevels::Spaced { count: 3, spacing: ContourSpacing::Logarithmic, range: Some((1e-2, 1.0)) }));
+        assert_eq!(ContourLevels::parse("lin 4"), Some(ContourLevels::Spaced { count: 4, spacing: ContourSpacing::Linear, range: None }));
+        for text in ["", "lin", "lin 0", "log 3 0 1", "lin 2 1", "0.1, a"].iter() {
+            assert_eq!(ContourLevels::parse(text), None, "{}", text);
+        }
+    }
+
+    #[test]
+    fn spaced_levels_lie_within_the_range() {
+        let linear = ContourLevels::parse("lin 3").unwrap();
+        assert_eq!(linear.values(&[0.0, f64::NAN, 4.0]), vec![1.0, 2.0, 3.0]);
+        let logarithmic = ContourLevels::parse("log 3 1e-2 1").unwrap();
+        let values = logarithmic.values(&[]);
+        assert!(values.iter().zip(&[1e-2, 1e-1, 1.0]).all(|(v, expected)| (v - expected).abs() < 1e-12));
+    }
+
+    #[test]
+    fn contour_of_a_cone_is_a_closed_circle() {
+        let patch = patch(41, |x, y| (x * x + y * y).sqrt());
+        let lines = contour_lines(&patch, 0.5, &[]);
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        assert_eq!(line.first(), line.last());
+        // Interpolated between grid points, which lie off the circle by at most half a cell diagonal
+        assert!(line.iter().all(|(x, y)| ((x * x + y * y).sqrt() - 0.5).abs() < 0.01));
+    }
+
+    #[test]
+    fn contours_end_at_the_border_and_skip_hidden_cells() {
+        let patch = patch(11, |x, _| x);
+        let lines = contour_lines(&patch, 0.1, &[]);
+        assert_eq!(lines.len(), 1);
+        let ys: Vec<f64> = lines[0].iter().map(|(_, y)| *y).collect();
+        assert_eq!((ys.iter().cloned().fold(f64::INFINITY, f64::min), ys.iter().cloned().fold(f64::NEG_INFINITY, f64::max)), (-1.0, 1.0));
+        assert!(lines[0].iter().all(|(x, _)| (x - 0.1).abs() < 1e-12));
+
+        // Hiding the middle splits the line in two
+        let lines = contour_lines(&patch, 0.1, &[((-1.0, 1.0), (-0.2, 0.2))]);
+        assert_eq!(lines.len(), 2);
+    }
+}
@@ -1,11 +1,202 @@
 use std::error::Error;
 use std::fmt;
+use std::io::BufRead;
+use std::sync::Arc;
+use crate::compression::Compression;
+use crate::lazy::{BlockIndex, OnDemandData};
+use crate::plane::{PlaneSeries, PlaneSeriesSlice};
+use crate::restart::RestartOverlap;
+use crate::scalars::TimeSeries;
+use crate::stream::StreamSource;
 type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
 
-type Time = f64;
+pub type Time = f64;
 
 pub type Point = (f64, f64);
-pub type DataLine = Vec<Point>;
+
+/// Information from the header line of a ygraph file, e.g.
+/// `"Output of X axis, variable ana.CSI, rank 0, grid 0, type CU, orientation: CE`,
+/// or from the comment header of a Carpet ASCII file
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMetadata {
+    pub axis: String,
+    pub variable: String,
+    pub rank: Option<u32>,
+    pub grid: Option<u32>,
+    pub output_type: Option<String>,
+    pub orientation: Option<String>,
+    pub refinement_level: Option<u32>,
+    pub component: Option<u32>,
+    pub series: Option<String>, // name of the series the line was exported from, if any
+}
+
+impl LineMetadata {
+    /// Parses a header line. Returns None if the line is not a header, or lacks the axis or variable.
+    pub fn from_header(line: &str) -> Option<LineMetadata> {
+        let header = line.trim_start_matches('"').strip_prefix("Output of ")?;
+
+        let mut axis = None;
+        let mut variable = None;
+        let mut rank = None;
+        let mut grid = None;
+        let mut output_type = None;
+        let mut orientation = None;
+
+        for field in header.split(',').map(|f| f.trim()) {
+            if let Some(a) = field.strip_suffix(" axis") {
+                axis = Some(a.to_string());
+                continue;
+            }
+            let (key, value) = match field.split_once(' ') {
+                Some((key, value)) => (key.trim_end_matches(':'), value.trim()),
+                None => continue,
+            };
+            match key {
+                "variable" => variable = Some(value.to_string()),
+                "rank" => rank = value.parse().ok(),
+                "grid" => grid = value.parse().ok(),
+                "type" => output_type = Some(value.to_string()),
+                "orientation" => orientation = Some(value.to_string()),
+                _ => {},
+            }
+        }
+
+        Some(LineMetadata {
+            axis: axis?,
+            variable: variable?,
+            rank,
+            grid,
+            output_type,
+            orientation,
+            refinement_level: None,
+            component: None,
+            series: None,
+        })
+    }
+
+    /// Writes the metadata as "# key = value" comment lines, as understood by `from_comments`
+    pub fn to_comments(&self) -> String {
+        let mut comments = format!("# axis = {}\n# variable = {}\n", self.axis, self.variable);
+        if let Some(rank) = self.rank {
+            comments.push_str(&format!("# rank = {}\n", rank));
+        }
+        if let Some(grid) = self.grid {
+            comments.push_str(&format!("# grid = {}\n", grid));
+        }
+        if let Some(output_type) = &self.output_type {
+            comments.push_str(&format!("# type = {}\n", output_type));
+        }
+        if let Some(orientation) = &self.orientation {
+            comments.push_str(&format!("# orientation = {}\n", orientation));
+        }
+        if let Some(refinement_level) = self.refinement_level {
+            comments.push_str(&format!("# refinement level = {}\n", refinement_level));
+        }
+        if let Some(component) = self.component {
+            comments.push_str(&format!("# component = {}\n", component));
+        }
+        comments
+    }
+
+    /// Builds metadata from (key, value) pairs read from comment lines. Returns None if the axis or
+    /// variable is missing, unless the name of the series is given, which then stands in for the
+    /// variable.
+    pub fn from_comments(comments: &[(String, String)]) -> Option<LineMetadata> {
+        let value = |key: &str| comments.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v.clone());
+        let series = value("series");
+        let (axis, variable) = match (value("axis"), value("variable"), &series) {
+            (Some(axis), Some(variable), _) => (axis, variable),
+            (axis, _, Some(series)) => (axis.unwrap_or_else(|| "x".to_string()), series.clone()),
+            _ => return None,
+        };
+        Some(LineMetadata {
+            axis,
+            variable,
+            rank: value("rank").and_then(|v| v.parse().ok()),
+            grid: value("grid").and_then(|v| v.parse().ok()),
+            output_type: value("type"),
+            orientation: value("orientation"),
+            refinement_level: value("refinement level").and_then(|v| v.parse().ok()),
+            component: value("component").and_then(|v| v.parse().ok()),
+            series,
+        })
+    }
+
+    /// Name of the series a line with this metadata belongs to, e.g. "ana.CSI (x)", unless it was
+    /// exported from a series of another name
+    pub fn series_name(&self) -> String {
+        match &self.series {
+            Some(series) => series.clone(),
+            None => format!("{} ({})", self.variable, self.axis.to_lowercase()),
+        }
+    }
+}
+
+/// What the values of a channel mean
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChannelKind {
+    Imaginary, // imaginary part of a complex value, whose real part is y
+    Uncertainty, // standard deviation σ of y
+}
+
+impl fmt::Display for ChannelKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChannelKind::Imaginary => write!(f, "imaginary"),
+            ChannelKind::Uncertainty => write!(f, "uncertainty"),
+        }
+    }
+}
+
+/// Values a line carries for each of its points besides x and y
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub kind: ChannelKind,
+    pub values: Vec<f64>, // one for each point
+}
+
+impl Channel {
+    pub fn new(kind: ChannelKind) -> Channel {
+        Channel {
+            kind,
+            values: Vec::new(),
+        }
+    }
+}
+
+/// A line of points, along with the header information of the file it was read from
+#[derive(Clone, Default, PartialEq)]
+pub struct DataLine {
+    pub points: Vec<Point>,
+    pub channels: Vec<Channel>, // kept as long as the points
+    pub metadata: Option<LineMetadata>,
+}
+
+impl DataLine {
+    /// An empty line, carrying channels of the given kinds
+    pub fn new(metadata: Option<LineMetadata>, kinds: &[ChannelKind]) -> DataLine {
+        DataLine {
+            points: Vec::new(),
+            channels: kinds.iter().map(|kind| Channel::new(*kind)).collect(),
+            metadata,
+        }
+    }
+
+    /// Adds a point along with its values for each channel, in the order of the channels. There must
+    /// be a value for each channel, so the channels stay as long as the points.
+    pub fn push(&mut self, point: Point, values: &[f64]) {
+        debug_assert_eq!(values.len(), self.channels.len(), "a value is needed for each channel");
+        self.points.push(point);
+        for (channel, value) in self.channels.iter_mut().zip(values) {
+            channel.values.push(*value);
+        }
+    }
+
+    /// The values of a channel, if the line has one of that kind
+    pub fn channel(&self, kind: ChannelKind) -> Option<&[f64]> {
+        self.channels.iter().find(|c| c.kind == kind).map(|c| &c.values[..])
+    }
+}
 
 #[derive(Clone)]
 pub struct DataSlice {
@@ -14,12 +205,28 @@ pub struct DataSlice {
 }
 
 impl DataSlice {
+    /// Writes each line as a separate block, preceded by comments with its time and metadata
     pub fn to_string_gnuplot(&self) -> String {
         let mut gnuplot_string = String::new();
 
         for dataline in &self.datalines {
-            for point in dataline {
-                gnuplot_string.push_str(&format!("{:.15e}\t{:.15e}\n", point.0, point.1));
+            gnuplot_string.push_str(&format!("# time = {:.15e}\n", self.time));
+            if let Some(metadata) = &dataline.metadata {
+                gnuplot_string.push_str(&metadata.to_comments());
+            }
+            // Channels follow x and y as further columns
+            let mut columns = "# columns = x y".to_string();
+            for channel in &dataline.channels {
+                columns.push_str(&format!(" {}", channel.kind));
+            }
+            gnuplot_string.push_str(&columns);
+            gnuplot_string.push('\n');
+            for (i, point) in dataline.points.iter().enumerate() {
+                gnuplot_string.push_str(&format!("{:.15e}\t{:.15e}", point.0, point.1));
+                for channel in &dataline.channels {
+                    gnuplot_string.push_str(&format!("\t{:.15e}", channel.values[i]));
+                }
+                gnuplot_string.push('\n');
             }
             gnuplot_string.push_str("\n\n");
         }
@@ -29,169 +236,1569 @@ impl DataSlice {
 
     pub fn sort(&mut self) {
         self.datalines.sort_by(|a, b| {
-            let min_a = a.iter().min_by(|(x1, _), (x2, _)| x1.partial_cmp(x2).unwrap());
-            let min_b = b.iter().min_by(|(x1, _), (x2, _)| x1.partial_cmp(x2).unwrap());
+            let min_a = a.points.iter().min_by(|(x1, _), (x2, _)| x1.partial_cmp(x2).unwrap());
+            let min_b = b.points.iter().min_by(|(x1, _), (x2, _)| x1.partial_cmp(x2).unwrap());
             min_a.partial_cmp(&min_b).unwrap()
         });
     }
 }
 
-#[derive(Debug)]
+/// Something wrong with a datafile, or with one of its lines
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReadProblem {
+    Unreadable(String), // the file could not be opened or read, with the reason
+    NoData,
+    BadTime(String), // a time header with a value that is not a number
+    TruncatedBlock, // a block that ends early, e.g. in a file that is still being written
+    MalformedLine,
+    MissingColumn(String), // a selected column that the header does not have
+}
+
+impl fmt::Display for ReadProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadProblem::Unreadable(reason) => write!(f, "unreadable file ({})", reason),
+            ReadProblem::NoData => write!(f, "no data found"),
+            ReadProblem::BadTime(value) => write!(f, "bad time value \"{}\"", value),
+            ReadProblem::TruncatedBlock => write!(f, "truncated block"),
+            ReadProblem::MalformedLine => write!(f, "malformed line"),
+            ReadProblem::MissingColumn(column) => write!(f, "no column \"{}\"", column),
+        }
+    }
+}
+
+/// Problems found while reading a file, with the numbers (counted from 1) of the lines they were found in
+pub type LineProblems = Vec<(usize, ReadProblem)>;
+
+/// Lines read from a file, with the problems found in it
+pub type FileContents = (Vec<(Time, DataLine)>, LineProblems);
+
+/// Parses the time of a block or row. Times that are not finite numbers are rejected, since steps
+/// are sorted and looked up by their time.
+pub fn parse_time(value: &str) -> Option<Time> {
+    value.parse::<f64>().ok().filter(|t| t.is_finite())
+}
+
+/// Records a problem in the last line of a file. Data cut off mid-line is a truncated block
+/// rather than a malformed line.
+pub fn mark_truncated(problems: &mut LineProblems, last_line: usize) {
+    if let Some((line, problem)) = problems.last_mut() {
+        if *line == last_line && *problem == ReadProblem::MalformedLine {
+            *problem = ReadProblem::TruncatedBlock;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct DatafileReadError {
-    filename: String,
+    pub filename: String,
+    pub line: Option<usize>,
+    pub problem: ReadProblem,
 }
 
 impl fmt::Display for DatafileReadError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Not a valid datafile: {}", self.filename)
+        match self.line {
+            Some(line) => write!(f, "{}, line {}: {}", self.filename, line, self.problem),
+            None => write!(f, "{}: {}", self.filename, self.problem),
+        }
     }
 }
 
 impl Error for DatafileReadError {}
 
+/// All problems found while loading a set of files, and how overlapping restarts were resolved
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+    pub errors: Vec<DatafileReadError>,
+    pub restarts: Vec<RestartOverlap>,
+}
+
+impl LoadReport {
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty() && self.restarts.is_empty()
+    }
+}
+
+impl fmt::Display for LoadReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for error in &self.errors {
+            writeln!(f, "{}", error)?;
+        }
+        for overlap in &self.restarts {
+            writeln!(f, "Restart overlap: {}", overlap)?;
+        }
+        Ok(())
+    }
+}
+
+/// A column of a file, given either by its number (counted from 1, like in Carpet file headers)
+/// or by its name in the file header
+#[derive(Debug, Clone, PartialEq)]
+pub enum Column {
+    Number(usize),
+    Name(String),
+}
+
+impl Column {
+    /// Parses a number or a name. Returns None for an empty string.
+    pub fn parse(s: &str) -> Option<Column> {
+        let s = s.trim();
+        if s.is_empty() {
+            None
+        } else if let Ok(number) = s.parse::<usize>() {
+            Some(Column::Number(number))
+        } else {
+            Some(Column::Name(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for Column {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Column::Number(number) => write!(f, "{}", number),
+            Column::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Which columns of a file to read time, x and y values from. None, or an empty list of y columns,
+/// leaves the choice to the reader. Imaginary parts and uncertainties go with the y columns in the
+/// same order, e.g. the first imaginary column with the first y column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnSelection {
+    pub time: Option<Column>,
+    pub x: Option<Column>,
+    pub y: Vec<Column>,
+    pub imaginary: Vec<Column>,
+    pub uncertainty: Vec<Column>,
+}
+
+impl ColumnSelection {
+    /// Whether several y columns are to be read, each making its own series
+    pub fn has_several_y(&self) -> bool {
+        self.y.len() > 1
+    }
+
+    /// The channels of the line of the i-th y column, with the columns they are read from
+    pub fn channels_of_y(&self, i: usize) -> Vec<(ChannelKind, &Column)> {
+        let imaginary = self.imaginary.get(i).map(|c| (ChannelKind::Imaginary, c));
+        let uncertainty = self.uncertainty.get(i).map(|c| (ChannelKind::Uncertainty, c));
+        imaginary.into_iter().chain(uncertainty).collect()
+    }
+
+    /// All columns read as channels of some y column
+    pub fn channel_columns(&self) -> impl Iterator<Item = &Column> {
+        self.imaginary.iter().chain(self.uncertainty.iter())
+    }
+}
+
+/// Columns of files whose rows are nothing but numbers, like ygraph files. Unless chosen otherwise,
+/// x is the first column and y the second. Columns can only be chosen by number in such files.
+pub(crate) struct PlainColumns {
+    x: usize, // counted from 0
+    y: Vec<usize>,
+    channels: Vec<Vec<(ChannelKind, usize)>>, // of each y column
+}
+
+/// The x value of a row, and each y value with the values of its channels
+pub(crate) type Row = (f64, Vec<(f64, Vec<f64>)>);
+
+impl PlainColumns {
+    pub(crate) fn resolve(columns: &ColumnSelection) -> PlainColumns {
+        let index = |column: &Column| match column {
+            Column::Number(number) if *number > 0 => Some(number - 1),
+            _ => None,
+        };
+        // With the position of each y column among the selected ones, which its channels go by
+        let y: Vec<(usize, usize)> = columns.y.iter().enumerate()
+            .filter_map(|(position, column)| index(column).map(|index| (position, index)))
+            .collect();
+        let y = if y.is_empty() { vec![(0, 1)] } else { y };
+        PlainColumns {
+            x: columns.x.as_ref().and_then(index).unwrap_or(0),
+            channels: y.iter().map(|(position, _)| {
+                columns.channels_of_y(*position).into_iter()
+                    .filter_map(|(kind, column)| index(column).map(|index| (kind, index)))
+                    .collect()
+            }).collect(),
+            y: y.iter().map(|(_, index)| *index).collect(),
+        }
+    }
+
+    /// Parses the x value, the y values and the values of their channels of a row. Returns None if
+    /// any of them is missing or not a number.
+    pub(crate) fn parse_row(&self, row: &str) -> Option<Row> {
+        let words: Vec<&str> = row.split_whitespace().collect();
+        let value = |index: usize| words.get(index).and_then(|w| w.parse::<f64>().ok());
+        let x = value(self.x)?;
+        let ys = self.y.iter().zip(&self.channels).map(|(index, channels)| {
+            let channel_values = channels.iter().map(|(_, index)| value(*index)).collect::<Option<Vec<f64>>>()?;
+            Some((value(*index)?, channel_values))
+        }).collect::<Option<Vec<(f64, Vec<f64>)>>>()?;
+        Some((x, ys))
+    }
+
+    /// Empty lines for each y column, with their channels. Lines of several y columns are told apart
+    /// by their column number, and named after their file if they have no header.
+    pub(crate) fn lines_of_columns(&self, metadata: &Option<LineMetadata>, filename: &str) -> Vec<DataLine> {
+        self.metadata_of_columns(metadata, filename).into_iter().zip(&self.channels)
+            .map(|(metadata, channels)| {
+                let kinds: Vec<ChannelKind> = channels.iter().map(|(kind, _)| *kind).collect();
+                DataLine::new(metadata, &kinds)
+            })
+            .collect()
+    }
+
+    fn metadata_of_columns(&self, metadata: &Option<LineMetadata>, filename: &str) -> Vec<Option<LineMetadata>> {
+        if self.y.len() == 1 {
+            return vec![metadata.clone()];
+        }
+        self.y.iter().map(|y| {
+            let mut metadata = metadata.clone().unwrap_or_else(|| LineMetadata {
+                axis: if self.x == 0 { "x".to_string() } else { format!("column {}", self.x + 1) },
+                variable: series_name_from_filename(filename),
+                rank: None,
+                grid: None,
+                output_type: None,
+                orientation: None,
+                refinement_level: None,
+                component: None,
+                series: None,
+            });
+            metadata.variable = format!("{} [column {}]", metadata.variable, y + 1);
+            metadata.series = metadata.series.map(|series| format!("{} [column {}]", series, y + 1));
+            Some(metadata)
+        }).collect()
+    }
+
+    /// Adds the points of a row to the lines of the y columns, the last ones of the given lines.
+    /// Points with a missing value, given as NaN, are left out.
+    pub(crate) fn push_row(&self, (x, ys): Row, lines: &mut [(Time, DataLine)]) {
+        let first_line = lines.len() - self.y.len();
+        for ((_, line), (y, channel_values)) in lines[first_line..].iter_mut().zip(ys) {
+            if !x.is_nan() && !y.is_nan() && !channel_values.iter().any(|v| v.is_nan()) {
+                line.push((x, y), &channel_values);
+            }
+        }
+    }
+}
+
+/// How far apart two times may be and still count as the same, e.g. when different processes
+/// print them with different rounding. Times match if they differ by no more than the absolute
+/// tolerance, or the relative tolerance times the larger of them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeTolerance {
+    pub relative: f64,
+    pub absolute: f64,
+}
+
+impl Default for TimeTolerance {
+    fn default() -> TimeTolerance {
+        TimeTolerance::new()
+    }
+}
+
+impl TimeTolerance {
+    /// Only exactly equal times match
+    pub fn new() -> TimeTolerance {
+        TimeTolerance {
+            relative: 0.0,
+            absolute: 0.0,
+        }
+    }
+
+    fn margin(&self, time: Time) -> f64 {
+        self.absolute.max(self.relative * time.abs())
+    }
+
+    pub fn matches(&self, t1: Time, t2: Time) -> bool {
+        (t1 - t2).abs() <= self.margin(t1.abs().max(t2.abs()))
+    }
+
+    /// The latest time that still matches the given one
+    pub fn latest_match(&self, time: Time) -> Time {
+        time + self.margin(time)
+    }
+
+    /// Finds the item whose time matches the given one in a list sorted by time, or else where an
+    /// item of that time belongs
+    pub fn search<T>(&self, items: &[T], time: Time, time_of: impl Fn(&T) -> Time) -> std::result::Result<usize, usize> {
+        let index = items.partition_point(|item| time_of(item) < time);
+        // Only the items right before and after the given time can be close enough
+        let candidates = [index.checked_sub(1), Some(index)];
+        let matching = candidates.iter().flatten()
+            .find(|i| matches!(items.get(**i), Some(item) if self.matches(time_of(item), time)));
+        match matching {
+            Some(i) => Ok(*i),
+            None => Err(index),
+        }
+    }
+}
+
+/// Which steps to read from files: those in a window of time, and of those every stride-th one.
+/// Lets very long runs be looked at in part, or as a coarse overview.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeFilter {
+    pub start: Option<Time>,
+    pub end: Option<Time>,
+    pub stride: usize,
+}
+
+impl Default for TimeFilter {
+    fn default() -> TimeFilter {
+        TimeFilter::new()
+    }
+}
+
+impl TimeFilter {
+    pub fn new() -> TimeFilter {
+        TimeFilter {
+            start: None,
+            end: None,
+            stride: 1,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.start.is_some() || self.end.is_some() || self.stride > 1
+    }
+
+    fn in_window(&self, time: Time, tolerance: &TimeTolerance) -> bool {
+        let after_start = match self.start {
+            Some(start) => time >= start || tolerance.matches(time, start),
+            None => true,
+        };
+        let before_end = match self.end {
+            Some(end) => time <= end || tolerance.matches(time, end),
+            None => true,
+        };
+        after_start && before_end
+    }
+}
+
+/// Decides block by block which steps of a file are read. Consecutive blocks of matching times,
+/// e.g. of several refinement levels, are one step. Steps are counted per file.
+#[derive(Debug, Clone)]
+pub struct StepSelector {
+    filter: TimeFilter,
+    tolerance: TimeTolerance,
+    last_time: Option<Time>,
+    skip: usize, // steps in the window still to be skipped before the next one kept
+    keeping: bool, // whether the step of the last time is kept
+}
+
+impl StepSelector {
+    pub fn new(filter: TimeFilter, tolerance: TimeTolerance) -> StepSelector {
+        StepSelector {
+            filter,
+            tolerance,
+            last_time: None,
+            skip: 0,
+            keeping: true,
+        }
+    }
+
+    /// Selects everything, for reading blocks that were already selected
+    pub fn all() -> StepSelector {
+        StepSelector::new(TimeFilter::new(), TimeTolerance::new())
+    }
+
+    /// Whether the lines of a block at this time are to be read
+    pub fn keeps(&mut self, time: Time) -> bool {
+        match self.last_time {
+            Some(last_time) if self.tolerance.matches(time, last_time) => return self.keeping,
+            _ => {},
+        }
+        self.last_time = Some(time);
+        self.keeping = false;
+        if self.filter.in_window(time, &self.tolerance) {
+            if self.skip == 0 {
+                self.keeping = true;
+                self.skip = self.filter.stride.max(1) - 1;
+            } else {
+                self.skip -= 1;
+            }
+        }
+        self.keeping
+    }
+}
+
+/// Options affecting how files are read
+#[derive(Debug, Clone)]
+pub struct LoadSettings {
+    pub columns: ColumnSelection,
+    pub time_tolerance: TimeTolerance,
+    pub time_filter: TimeFilter,
+    pub on_demand: bool, // Only index files when loading them, and read each step when it is shown
+    pub merge_restarts: bool, // Keep only the newest segment where output directories overlap in time, off by default
+}
+
+impl Default for LoadSettings {
+    fn default() -> LoadSettings {
+        LoadSettings::new()
+    }
+}
+
+impl LoadSettings {
+    pub fn new() -> LoadSettings {
+        LoadSettings {
+            columns: ColumnSelection {
+                time: None,
+                x: None,
+                y: Vec::new(),
+                imaginary: Vec::new(),
+                uncertainty: Vec::new(),
+            },
+            time_tolerance: TimeTolerance::new(),
+            time_filter: TimeFilter::new(),
+            on_demand: false,
+            merge_restarts: false,
+        }
+    }
+}
+
+/// The slice of a single series at some time, labeled with the name of that series
+#[derive(Clone)]
+pub struct SeriesSlice {
+    pub name: String,
+    pub slice: Arc<DataSlice>,
+}
+
+/// Writes the slices of several series into one gnuplot file, with the name of each series as a comment
+pub fn to_string_gnuplot(series_slices: &[SeriesSlice]) -> String {
+    let mut gnuplot_string = String::new();
+
+    for series_slice in series_slices {
+        gnuplot_string.push_str(&format!("# series = {}\n", series_slice.name));
+        gnuplot_string.push_str(&series_slice.slice.to_string_gnuplot());
+    }
+
+    gnuplot_string
+}
+
+/// A named sequence of dataslices, e.g. everything read for one variable. Slices are shared with
+/// whatever displays them, so stepping through the data does not copy any points.
+#[derive(Clone)]
+pub struct Series {
+    pub name: String,
+    pub dataslices: Vec<Arc<DataSlice>>, // sorted by time
+}
+
+impl Series {
+    /// Collects (time, line) pairs into slices of matching time. A slice takes the earliest of its times.
+    fn from_time_line_pairs(name: String, mut time_line_pairs: Vec<(Time, DataLine)>, tolerance: &TimeTolerance) -> Series {
+        let mut series = Series {
+            name,
+            dataslices: Vec::new(),
+        };
+
+        // Sort all datalines by time
+        time_line_pairs.sort_by(|(t1, _), (t2, _)| t1.total_cmp(t2));
+
+        // Collect datalines by their time
+        let mut dataslices: Vec<DataSlice> = Vec::new();
+        for (t, l) in time_line_pairs.into_iter() {
+            // If a slice already exists and the time matches, add line to that
+            // slice. Otherwise create a new one.
+            if let Some(slice) = dataslices.last_mut() {
+                if tolerance.matches(slice.time, t) {
+                    slice.datalines.push(l);
+                } else {
+                    dataslices.push(DataSlice {
+                        time: t,
+                        datalines: vec![l]
+                    });
+                }
+            } else {
+                dataslices.push(DataSlice {
+                    time: t,
+                    datalines: vec![l]
+                });
+            }
+        }
+
+        for mut slice in dataslices {
+            slice.sort();
+            series.dataslices.push(Arc::new(slice));
+        }
+
+        series
+    }
+
+    /// Adds a line to the slice of matching time, or to a new slice at the right place
+    fn insert_line(&mut self, time: Time, line: DataLine, tolerance: &TimeTolerance) {
+        match tolerance.search(&self.dataslices, time, |s| s.time) {
+            Ok(index) => {
+                // Copies the slice only if it is being displayed
+                let slice = Arc::make_mut(&mut self.dataslices[index]);
+                slice.datalines.push(line);
+                slice.sort();
+            },
+            Err(index) => self.dataslices.insert(index, Arc::new(DataSlice {
+                time,
+                datalines: vec![line],
+            })),
+        }
+    }
+
+    /// Removes a line from the slice of matching time, and the slice if it ends up empty
+    fn remove_line(&mut self, time: Time, line: &DataLine, tolerance: &TimeTolerance) {
+        if let Ok(index) = tolerance.search(&self.dataslices, time, |s| s.time) {
+            let slice = Arc::make_mut(&mut self.dataslices[index]);
+            if let Some(line_index) = slice.datalines.iter().position(|l| l == line) {
+                slice.datalines.remove(line_index);
+            }
+            if slice.datalines.is_empty() {
+                self.dataslices.remove(index);
+            }
+        }
+    }
+
+    /// Returns the latest slice at or before the given time (or close enough to it), if there is one
+    pub fn at_time(&self, time: Time, tolerance: &TimeTolerance) -> Option<Arc<DataSlice>> {
+        let latest = tolerance.latest_match(time);
+        let index = self.dataslices.partition_point(|s| s.time <= latest);
+        index.checked_sub(1).map(|i| Arc::clone(&self.dataslices[i]))
+    }
+
+    pub fn times(&self) -> Vec<Time> {
+        self.dataslices.iter().map(|s| s.time).collect()
+    }
+
+    /// Adds the slices of another series, combining slices of matching time
+    fn merge(&mut self, other: Series, tolerance: &TimeTolerance) {
+        for slice in other.dataslices {
+            match tolerance.search(&self.dataslices, slice.time, |s| s.time) {
+                Ok(index) => {
+                    let existing = Arc::make_mut(&mut self.dataslices[index]);
+                    existing.datalines.extend(slice.datalines.iter().cloned());
+                    existing.sort();
+                },
+                Err(index) => self.dataslices.insert(index, slice),
+            }
+        }
+    }
+
+    /// Removes the slices from the given time on. Returns the number of lines removed.
+    fn truncate_from(&mut self, time: Time, tolerance: &TimeTolerance) -> usize {
+        let index = match tolerance.search(&self.dataslices, time, |s| s.time) {
+            Ok(index) | Err(index) => index,
+        };
+        self.dataslices.drain(index..).map(|s| s.datalines.len()).sum()
+    }
+}
+
+/// Derives a series name from a filename, e.g. "rho" from "/path/to/rho.xg"
+fn series_name_from_filename(filename: &str) -> String {
+    use crate::compression::strip_compression_extension;
+    use std::path::Path;
+
+    match Path::new(strip_compression_extension(filename)).file_stem() {
+        Some(stem) => stem.to_string_lossy().to_string(),
+        None => filename.to_string(),
+    }
+}
+
+/// Name of the series a line belongs to: from its metadata if it has any, otherwise from its file
+pub(crate) fn series_name_of_line(line: &DataLine, filename: &str) -> String {
+    match &line.metadata {
+        Some(metadata) => metadata.series_name(),
+        None => series_name_from_filename(filename),
+    }
+}
+
+/// Adds a line to the series of the given name, creating the series if needed
+fn insert_line_into_series(series: &mut Vec<Series>, name: String, time: Time, line: DataLine, tolerance: &TimeTolerance) {
+    let index = match series.iter().position(|s| s.name == name) {
+        Some(index) => index,
+        None => {
+            series.push(Series {
+                name,
+                dataslices: Vec::new(),
+            });
+            series.len() - 1
+        },
+    };
+    series[index].insert_line(time, line, tolerance);
+}
+
+/// Lines superseded by, and lines read from, data appended to a file
+type AppendedLines = (Vec<(Time, DataLine)>, Vec<(Time, DataLine)>);
+
+/// A file data was read from, and how far it has been read. Blocks of data are complete once they
+/// are followed by a blank line. Once the file is followed, lines from an incomplete block at its
+/// end are kept around, so they can be replaced once the block has been written in full.
+#[derive(Clone)]
+pub struct SourceFile {
+    pub filename: String,
+    format: FileFormat,
+    compression: Compression,
+    length: u64, // bytes seen so far
+    complete_length: Option<u64>, // bytes up to the end of the last complete block, once followed
+    pending_pairs: Vec<(Time, DataLine)>, // lines read from after complete_length
+    header: Vec<u8>, // leading comments, describing the columns of Carpet files
+    metadata: Option<LineMetadata>, // latest header seen, for lines read without one
+    selector: StepSelector, // steps of appended data are counted from the first one appended
+}
+
+impl SourceFile {
+    /// Records how far a file has been read, after all of it was read into the given pairs
+    fn new(filename: &str, time_line_pairs: &[(Time, DataLine)], settings: &LoadSettings) -> Result<SourceFile> {
+        use crate::compression::{open_file, strip_compression_extension};
+
+        let length = std::fs::metadata(filename)?.len();
+        let (compression, mut reader) = open_file(filename)?;
+        let format = FileFormat::detect(strip_compression_extension(filename), reader.fill_buf()?);
+
+        Ok(SourceFile {
+            filename: filename.to_string(),
+            format,
+            compression,
+            length,
+            complete_length: None,
+            pending_pairs: Vec::new(),
+            header: Vec::new(),
+            metadata: time_line_pairs.iter().rev().find_map(|(_, l)| l.metadata.clone()),
+            selector: StepSelector::new(settings.time_filter, settings.time_tolerance),
+        })
+    }
+
+    /// Starts following the file from where it was loaded: finds the end of the last complete block
+    /// and reads the lines after it, which are replaced once their block is complete. Returns the
+    /// end of the last complete block.
+    fn start_following(&mut self, settings: &LoadSettings) -> Result<u64> {
+        use std::fs::File;
+        use std::io::{BufReader, Read};
+
+        let mut file = File::open(&self.filename)?;
+        let complete_length = complete_length(&mut file, self.length)?;
+        if let FileFormat::CarpetAscii = self.format {
+            self.header = leading_comments(BufReader::new(File::open(&self.filename)?.take(self.length)))?;
+        }
+        self.pending_pairs = self.read_range(complete_length, self.length, settings, &mut self.selector.clone())?;
+        self.complete_length = Some(complete_length);
+        Ok(complete_length)
+    }
+
+    /// Only uncompressed files in formats that are written block by block can be followed while they grow
+    fn can_follow(&self) -> bool {
+        self.compression == Compression::None && !matches!(self.format, FileFormat::Delimited(_))
+    }
+
+    /// Reads the lines between two byte offsets, after the leading comments of the file. Lines without
+    /// a header take the latest one seen. Problems are not reported again, since line numbers only
+    /// count from the start offset.
+    fn read_range(&self, start: u64, end: u64, settings: &LoadSettings, selector: &mut StepSelector) -> Result<Vec<(Time, DataLine)>> {
+        use std::fs::File;
+        use std::io::{BufReader, Read, Seek, SeekFrom};
+
+        let mut file = File::open(&self.filename)?;
+        file.seek(SeekFrom::Start(start))?;
+        let reader = BufReader::new(self.header.as_slice().chain(file.take(end - start)));
+
+        let mut time_line_pairs = read_datalines(reader, self.format, &self.filename, settings, selector, &mut LineProblems::new())?;
+        for (_, line) in time_line_pairs.iter_mut() {
+            if line.metadata.is_none() {
+                line.metadata = self.metadata.clone();
+            }
+        }
+        Ok(time_line_pairs)
+    }
+
+    /// Reads whatever has been appended to the file since it was last read. Returns the lines that
+    /// are superseded, and the lines that were read, or None if the file has not grown.
+    fn read_appended(&mut self, settings: &LoadSettings) -> Result<Option<AppendedLines>> {
+        use std::fs::File;
+
+        let mut file = File::open(&self.filename)?;
+        let length = file.metadata()?.len();
+        if length <= self.length || !self.can_follow() {
+            return Ok(None);
+        }
+        let previous_complete_length = match self.complete_length {
+            Some(complete_length) => complete_length,
+            None => self.start_following(settings)?,
+        };
+
+        let complete_length = complete_length(&mut file, length)?.max(previous_complete_length);
+        let mut selector = self.selector.clone();
+        let mut new_pairs = self.read_range(previous_complete_length, complete_length, settings, &mut selector)?;
+        if let Some(metadata) = new_pairs.iter().rev().find_map(|(_, l)| l.metadata.clone()) {
+            self.metadata = Some(metadata);
+        }
+        // Pending lines are read again once complete, so they do not count towards the steps yet
+        let pending_pairs = self.read_range(complete_length, length, settings, &mut selector.clone())?;
+        new_pairs.extend(pending_pairs.iter().cloned());
+
+        let superseded_pairs = std::mem::replace(&mut self.pending_pairs, pending_pairs);
+        self.length = length;
+        self.complete_length = Some(complete_length);
+        self.selector = selector;
+
+        Ok(Some((superseded_pairs, new_pairs)))
+    }
+}
+
+/// Reads the comment lines at the start of a file, up to its first line of data
+fn leading_comments<R: BufRead>(mut reader: R) -> Result<Vec<u8>> {
+    let mut header = Vec::new();
+    let mut line = Vec::new();
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        let trimmed = String::from_utf8_lossy(&line);
+        let trimmed = trimmed.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            break;
+        }
+        header.append(&mut line);
+    }
+    Ok(header)
+}
+
+/// Finds the end of the last blank line in a file, searching backwards from its end
+fn complete_length(file: &mut std::fs::File, length: u64) -> Result<u64> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    const CHUNK_SIZE: u64 = 64 * 1024;
+    let mut end = length;
+    let mut buffer = Vec::new();
+    while end > 0 {
+        // Chunks overlap by one byte, so a blank line on a chunk boundary is not missed
+        let start = end.saturating_sub(CHUNK_SIZE);
+        let chunk_end = (end + 1).min(length);
+        buffer.resize((chunk_end - start) as usize, 0);
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut buffer)?;
+        if let Some(position) = buffer.windows(2).rposition(|w| w == b"\n\n") {
+            return Ok(start + position as u64 + 2);
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
 #[derive(Clone)]
 pub struct Data {
-    pub dataslices: Vec<DataSlice>,
+    pub series: Vec<Series>,
+    pub planes: Vec<PlaneSeries>, // 2D output, read completely and not followed
+    pub time_series: Vec<TimeSeries>, // 0D output, likewise
+    pub sources: Vec<SourceFile>,
+    pub on_demand: Option<OnDemandData>, // Series read from indexed files as needed
+    pub streams: Vec<StreamSource>, // standard input and named pipes, still being read
     pub start_time: f64,
     pub end_time: f64,
+    pub time_tolerance: TimeTolerance, // for matching times of different series
 }
 
 impl Data {
+    /// Data without any series yet, e.g. for lines to be added as they are received
+    pub fn empty(settings: &LoadSettings) -> Data {
+        let mut data = Data::new();
+        data.time_tolerance = settings.time_tolerance;
+        data
+    }
+
     fn new() -> Data {
         Data{
-            dataslices: Vec::new(),
+            series: Vec::new(),
+            planes: Vec::new(),
+            time_series: Vec::new(),
+            sources: Vec::new(),
+            on_demand: None,
+            streams: Vec::new(),
             start_time: 0.0,
             end_time: 0.0,
+            time_tolerance: TimeTolerance::new(),
         }
     }
 
-    pub fn from_files(filenames: Vec<String>) -> Option<Data> {
+    /// Reads data from a set of files. Lines of the same variable and axis are collected into the same
+    /// series, e.g. output of one variable from several processes. Lines without a header are grouped
+    /// by the name of their file (minus the extension). Whatever can be read is loaded, problems with
+    /// files and lines that could not be read are collected in the report. Large files are read from
+    /// their cache if it is up to date. If asked for, files that can be indexed are read on demand.
+    /// Standard input ("-") and named pipes are read in the background, see `receive_streamed`.
+    /// 2D output is read into planes, one series for each variable and plane. 0D output is read into
+    /// time series, one for each reduction of each variable.
+    pub fn from_files(filenames: Vec<String>, settings: &LoadSettings) -> (Option<Data>, LoadReport) {
         use indicatif::{ProgressBar, ProgressStyle};
+        use rayon::prelude::*;
+        use crate::restart::{directory_of, is_superseded, Segments};
+        use std::collections::HashMap;
         let pb_style = ProgressStyle::default_bar()
             .template("{msg} [{pos}/{len}] {wide_bar}");
         let mut data = Data::new();
+        data.time_tolerance = settings.time_tolerance;
 
-        // Collect data from all files, tagged with their time values
+        let (stream_filenames, filenames): (Vec<String>, Vec<String>) = filenames.into_iter()
+            .partition(|filename| crate::stream::is_stream(filename));
+        data.streams = stream_filenames.iter().map(|filename| StreamSource::new(filename, settings)).collect();
+
+        let (plane_filenames, filenames): (Vec<String>, Vec<String>) = filenames.into_iter()
+            .partition(|filename| crate::plane::is_plane_file(filename));
+        let (scalar_filenames, filenames): (Vec<String>, Vec<String>) = filenames.into_iter()
+            .partition(|filename| crate::scalars::is_scalar_file(filename));
+
+        let (indexed_filenames, filenames): (Vec<String>, Vec<String>) = filenames.into_iter()
+            .partition(|filename| settings.on_demand && crate::lazy::can_index(filename, settings));
+
+        // Read all files in parallel. Results are collected in the order the files were given,
+        // so the data is the same no matter which file finished first.
         let progress_bar = ProgressBar::new(filenames.len() as u64)
             .with_message("Loading files:");
         progress_bar.set_style(pb_style.clone());
-        let mut time_line_pairs = Vec::new();
-        for filename in filenames {
-            if let Ok(mut pairs) = read_datalines_from_file(&filename) {
-                time_line_pairs.append(&mut pairs);
+        let file_results: Vec<_> = filenames
+            .into_par_iter()
+            .map(|filename| {
+                let result = read_datalines_cached(&filename, settings).map(|(pairs, problems)| {
+                    let source = SourceFile::new(&filename, &pairs, settings).ok();
+                    (pairs, problems, source)
+                }).map_err(|e| e.to_string());
+                progress_bar.inc(1);
+                (filename, result)
+            })
+            .collect();
+        progress_bar.finish_with_message("Finished loading files");
+
+        // Files that could not be read at all are left out
+        let mut report = LoadReport::default();
+        let mut file_data = Vec::new();
+        for (filename, result) in file_results {
+            let error = |line, problem| DatafileReadError {
+                filename: filename.clone(),
+                line,
+                problem,
+            };
+            match result {
+                Ok((pairs, problems, source)) => {
+                    report.errors.extend(problems.into_iter().map(|(line, problem)| error(Some(line), problem)));
+                    // A time filter may well leave nothing of a file
+                    if pairs.is_empty() && !settings.time_filter.is_active() {
+                        report.errors.push(error(None, ReadProblem::NoData));
+                    }
+                    file_data.push((filename, pairs, source));
+                },
+                Err(reason) => report.errors.push(error(None, ReadProblem::Unreadable(reason))),
             }
-            progress_bar.inc(1);
         }
-        progress_bar.finish_with_message("Finished loading files");
 
-        // No data in files means no data for structure
-        if time_line_pairs.is_empty() {
-            return None;
+        data.planes = read_planes(plane_filenames, settings, &mut report);
+        data.time_series = read_time_series(scalar_filenames, settings, &mut report);
+
+        if !indexed_filenames.is_empty() {
+            let on_demand = OnDemandData::from_files(&indexed_filenames, settings, &mut report);
+            data.on_demand = Some(on_demand).filter(|d| !d.is_empty());
+        }
+
+        // Output of a restarted run overlaps where an older segment went on past the checkpoint
+        let mut overlaps = Vec::new();
+        if settings.merge_restarts {
+            let mut segments = Segments::new();
+            for (filename, pairs, _) in &file_data {
+                let directory = directory_of(filename);
+                for (t, l) in pairs {
+                    segments.add(&series_name_of_line(l, filename), &directory, *t);
+                }
+            }
+            overlaps = segments.overlaps(&settings.time_tolerance);
         }
 
-        let progress_bar = ProgressBar::new(time_line_pairs.len() as u64)
+        // Collect data from all files, tagged with their time values and grouped by series name
+        let mut named_pairs: Vec<(String, Vec<(Time, DataLine)>)> = Vec::new();
+        let mut series_indices: HashMap<String, usize> = HashMap::new();
+        for (filename, pairs, source) in file_data {
+            if let Some(source) = source {
+                data.sources.push(source);
+            }
+            let directory = directory_of(&filename);
+            for (t, l) in pairs {
+                let name = series_name_of_line(&l, &filename);
+                if is_superseded(&mut overlaps, &name, &directory, t, &settings.time_tolerance) {
+                    continue;
+                }
+                match series_indices.get(&name) {
+                    Some(index) => named_pairs[*index].1.push((t, l)),
+                    None => {
+                        series_indices.insert(name.clone(), named_pairs.len());
+                        named_pairs.push((name, vec![(t, l)]));
+                    },
+                }
+            }
+        }
+
+        report.restarts.extend(overlaps);
+
+        let progress_bar = ProgressBar::new(named_pairs.len() as u64)
             .with_message("Processing data:");
-        progress_bar.set_style(pb_style.clone());
+        progress_bar.set_style(pb_style);
 
-        // Sort all datalines by time
-        time_line_pairs.sort_by(|(t1, _), (t2, _)| t1.partial_cmp(t2).unwrap());
+        // No data in files means no series
+        data.series = named_pairs
+            .into_par_iter()
+            .filter(|(_, pairs)| !pairs.is_empty())
+            .map(|(name, pairs)| {
+                let series = Series::from_time_line_pairs(name, pairs, &settings.time_tolerance);
+                progress_bar.inc(1);
+                series
+            })
+            .collect();
 
-        // Extract first and last time in data
-        data.start_time = time_line_pairs.first().unwrap().0;
-        data.end_time = time_line_pairs.last().unwrap().0;
+        progress_bar.finish_with_message("Finished processing data");
 
-        // Collect datalines by their time
-        for (t, l) in time_line_pairs.into_iter() {
-            // If a slice already exists and the time matches, add line to that
-            // slice. Otherwise create a new one.
-            if let Some(slice) = data.dataslices.last_mut() {
-                if slice.time == t {
-                    slice.datalines.push(l);
-                } else {
-                    data.dataslices.push(DataSlice {
-                        time: t,
-                        datalines: vec![l]
-                    });
+        if data.series.is_empty() && data.planes.is_empty() && data.time_series.is_empty() && data.on_demand.is_none() && data.streams.is_empty() {
+            return (None, report);
+        }
+
+        data.update_time_range();
+
+        (Some(data), report)
+    }
+
+    /// Adds data read from more files, e.g. another variable or another segment of a restarted run.
+    /// A series that is added to and whose time range overlaps the added data, but starts at a
+    /// different time, is taken to be a restarted run. Only the newer segment is kept where they
+    /// overlap, unless restarts are not to be merged. Returns the overlaps found.
+    pub fn merge(&mut self, other: Data, settings: &LoadSettings) -> Vec<RestartOverlap> {
+        use crate::restart::is_restart_of;
+        let tolerance = &self.time_tolerance;
+        let mut overlaps = Vec::new();
+
+        for mut added in other.series {
+            let existing = match self.series.iter_mut().find(|s| s.name == added.name) {
+                Some(existing) => existing,
+                None => {
+                    self.series.push(added);
+                    continue;
+                },
+            };
+
+            let range = |series: &Series| match (series.dataslices.first(), series.dataslices.last()) {
+                (Some(first), Some(last)) => Some((first.time, last.time)),
+                _ => None,
+            };
+            if let (true, Some((start, end)), Some((added_start, added_end))) = (settings.merge_restarts, range(existing), range(&added)) {
+                let name = added.name.clone();
+                let overlap = |older: &str, newer: &str, from_time: Time, dropped_lines: usize| RestartOverlap {
+                    series: name.clone(),
+                    older: older.to_string(),
+                    newer: newer.to_string(),
+                    from_time,
+                    dropped_lines,
+                };
+                // Series of the same start are kept whole, e.g. output of another process or run
+                if is_restart_of((start, end), added_start, tolerance) {
+                    let dropped_lines = existing.truncate_from(added_start, tolerance);
+                    overlaps.push(overlap("loaded data", "added files", added_start, dropped_lines));
+                } else if is_restart_of((added_start, added_end), start, tolerance) {
+                    let dropped_lines = added.truncate_from(start, tolerance);
+                    overlaps.push(overlap("added files", "loaded data", start, dropped_lines));
                 }
-            } else {
-                data.dataslices.push(DataSlice {
-                    time: t,
-                    datalines: vec![l]
-                });
             }
-            progress_bar.inc(1);
+            existing.merge(added, tolerance);
         }
 
-        for slice in data.dataslices.iter_mut() {
-            slice.sort();
+        for added in other.planes {
+            match self.planes.iter_mut().find(|p| p.name == added.name) {
+                Some(existing) => existing.merge(added, tolerance),
+                None => self.planes.push(added),
+            }
         }
 
-        progress_bar.finish_with_message("Finished processing data");
+        for added in other.time_series {
+            match self.time_series.iter_mut().find(|s| s.name == added.name) {
+                Some(existing) => existing.merge(added, tolerance),
+                None => self.time_series.push(added),
+            }
+        }
 
-        if data.dataslices.is_empty() {
-            None
-        } else {
-            Some(data)
+        self.sources.extend(other.sources);
+        self.streams.extend(other.streams);
+        self.on_demand = match (self.on_demand.take(), other.on_demand) {
+            (Some(mut on_demand), Some(added)) => {
+                on_demand.merge(added);
+                Some(on_demand)
+            },
+            (on_demand, added) => on_demand.or(added),
+        };
+
+        self.series.retain(|s| !s.dataslices.is_empty());
+        self.update_time_range();
+        overlaps
+    }
+
+    /// Extracts first and last time in data
+    fn update_time_range(&mut self) {
+        let times = self.times();
+        if let (Some(first), Some(last)) = (times.first(), times.last()) {
+            self.start_time = *first;
+            self.end_time = *last;
         }
     }
 
-    pub fn at_time(&self, time: Time) -> DataSlice {
-        let index_option = self.dataslices.iter().position(|s| s.time >= time);
-        if let Some(index) = index_option {
-            self.dataslices[index as usize].clone()
-        } else {
-            self.dataslices.last().unwrap().clone()
+    /// Reads data appended to the source files since they were last read, e.g. by a simulation that
+    /// is still running. Returns whether anything changed.
+    pub fn update_from_files(&mut self, settings: &LoadSettings) -> bool {
+        let mut updated = false;
+
+        for source in self.sources.iter_mut() {
+            if let Ok(Some((superseded_pairs, new_pairs))) = source.read_appended(settings) {
+                for (t, l) in superseded_pairs {
+                    let name = series_name_of_line(&l, &source.filename);
+                    if let Some(series) = self.series.iter_mut().find(|s| s.name == name) {
+                        series.remove_line(t, &l, &settings.time_tolerance);
+                    }
+                }
+                for (t, l) in new_pairs {
+                    let name = series_name_of_line(&l, &source.filename);
+                    insert_line_into_series(&mut self.series, name, t, l, &settings.time_tolerance);
+                }
+                updated = true;
+            }
+        }
+
+        if updated {
+            self.series.retain(|s| !s.dataslices.is_empty());
+            self.update_time_range();
+        }
+        updated
+    }
+
+    /// Adds the blocks that arrived through streams since the last call. Returns whether there were any.
+    pub fn receive_streamed(&mut self) -> bool {
+        let lines: Vec<(String, Time, DataLine)> = self.streams.iter()
+            .flat_map(|stream| stream.receive().into_iter().map(move |(t, l)| (series_name_of_line(&l, &stream.name), t, l)))
+            .collect();
+        self.add_lines(lines)
+    }
+
+    /// Adds lines to the series of the given names. Returns whether there were any.
+    pub fn add_lines(&mut self, lines: Vec<(String, Time, DataLine)>) -> bool {
+        if lines.is_empty() {
+            return false;
+        }
+        for (name, t, l) in lines {
+            insert_line_into_series(&mut self.series, name, t, l, &self.time_tolerance);
+        }
+        self.update_time_range();
+        true
+    }
+
+    /// Returns the slice of each series that is current at the given time. Series that have not started
+    /// yet are left out.
+    pub fn at_time(&self, time: Time) -> Vec<SeriesSlice> {
+        // Times before the start of the data show the first step
+        let time = time.max(self.start_time);
+
+        let mut series_slices: Vec<SeriesSlice> = self.series.iter().filter_map(|series| {
+            series.at_time(time, &self.time_tolerance).map(|slice| SeriesSlice {
+                name: series.name.clone(),
+                slice,
+            })
+        }).collect();
+        // Series can be made of files read both ways
+        if let Some(on_demand) = &self.on_demand {
+            for series_slice in on_demand.at_time(time) {
+                match series_slices.iter_mut().find(|s| s.name == series_slice.name) {
+                    // Like within a series, only the latest slice counts
+                    Some(existing) => if self.time_tolerance.matches(series_slice.slice.time, existing.slice.time) {
+                        let slice = Arc::make_mut(&mut existing.slice);
+                        slice.datalines.extend(series_slice.slice.datalines.iter().cloned());
+                        slice.sort();
+                    } else if series_slice.slice.time > existing.slice.time {
+                        *existing = series_slice;
+                    },
+                    None => series_slices.push(series_slice),
+                }
+            }
         }
+        series_slices
     }
 
+    /// Returns the slice of each plane series that is current at the given time
+    pub fn planes_at_time(&self, time: Time) -> Vec<PlaneSeriesSlice> {
+        let time = time.max(self.start_time);
+        self.planes.iter().filter_map(|plane| {
+            plane.at_time(time, &self.time_tolerance).map(|slice| PlaneSeriesSlice {
+                name: plane.name.clone(),
+                metadata: plane.metadata.clone(),
+                slice,
+            })
+        }).collect()
+    }
+
+    /// Returns the sorted time values of all series combined. Time series usually have a value at
+    /// every iteration, so their times only count if nothing else is loaded.
     pub fn times(&self) -> Vec<Time> {
-        self.dataslices.iter().map(|s| s.time).collect()
+        let mut times: Vec<Time> = self.series.iter().flat_map(|s| s.times()).collect();
+        times.extend(self.planes.iter().flat_map(|p| p.times()));
+        if let Some(on_demand) = &self.on_demand {
+            times.extend(on_demand.times());
+        }
+        if times.is_empty() {
+            times.extend(self.time_series.iter().flat_map(|s| s.times()));
+        }
+        times.sort_by(|t1, t2| t1.total_cmp(t2));
+        // Times of different series that match count as one step
+        let tolerance = self.time_tolerance;
+        times.dedup_by(|t, previous| tolerance.matches(*previous, *t));
+        times
+    }
+}
+
+/// Reads 2D files in parallel, collecting their patches into one series for each variable and plane
+fn read_planes(filenames: Vec<String>, settings: &LoadSettings, report: &mut LoadReport) -> Vec<PlaneSeries> {
+    use crate::plane::read_plane_file;
+    use rayon::prelude::*;
+
+    let file_results: Vec<_> = filenames
+        .into_par_iter()
+        .map(|filename| {
+            let result = read_plane_file(&filename, settings).map_err(|e| e.to_string());
+            (filename, result)
+        })
+        .collect();
+
+    let mut planes: Vec<PlaneSeries> = Vec::new();
+    for (filename, result) in file_results {
+        let error = |line, problem| DatafileReadError {
+            filename: filename.clone(),
+            line,
+            problem,
+        };
+        match result {
+            Ok((metadata, pairs, problems)) => {
+                report.errors.extend(problems.into_iter().map(|(line, problem)| error(Some(line), problem)));
+                if pairs.is_empty() {
+                    if !settings.time_filter.is_active() {
+                        report.errors.push(error(None, ReadProblem::NoData));
+                    }
+                    continue;
+                }
+                let added = PlaneSeries::from_time_patch_pairs(metadata, pairs, &settings.time_tolerance);
+                match planes.iter_mut().find(|p| p.name == added.name) {
+                    Some(existing) => existing.merge(added, &settings.time_tolerance),
+                    None => planes.push(added),
+                }
+            },
+            Err(reason) => report.errors.push(error(None, ReadProblem::Unreadable(reason))),
+        }
+    }
+    planes
+}
+
+/// Reads 0D files in parallel, collecting their values into one time series for each reduction of
+/// each variable
+fn read_time_series(filenames: Vec<String>, settings: &LoadSettings, report: &mut LoadReport) -> Vec<TimeSeries> {
+    use crate::scalars::read_scalar_file;
+    use rayon::prelude::*;
+
+    let file_results: Vec<_> = filenames
+        .into_par_iter()
+        .map(|filename| {
+            let result = read_scalar_file(&filename, settings).map_err(|e| e.to_string());
+            (filename, result)
+        })
+        .collect();
+
+    let mut time_series: Vec<TimeSeries> = Vec::new();
+    for (filename, result) in file_results {
+        let error = |line, problem| DatafileReadError {
+            filename: filename.clone(),
+            line,
+            problem,
+        };
+        match result {
+            Ok((added_series, problems)) => {
+                report.errors.extend(problems.into_iter().map(|(line, problem)| error(Some(line), problem)));
+                if added_series.iter().all(|s| s.points().is_empty()) {
+                    if !settings.time_filter.is_active() {
+                        report.errors.push(error(None, ReadProblem::NoData));
+                    }
+                    continue;
+                }
+                for added in added_series {
+                    match time_series.iter_mut().find(|s| s.name == added.name) {
+                        Some(existing) => existing.merge(added, &settings.time_tolerance),
+                        None => time_series.push(added),
+                    }
+                }
+            },
+            Err(reason) => report.errors.push(error(None, ReadProblem::Unreadable(reason))),
+        }
     }
+    time_series
 }
 
-fn read_datalines_from_file(filename: &str) -> Result<Vec<(Time, DataLine)>> {
-    use std::fs::File;
-    use std::io::BufReader;
-    use std::io::prelude::*;
+/// The file formats muninn can read
+#[derive(Clone, Copy)]
+pub(crate) enum FileFormat {
+    YGraph,
+    CarpetAscii,
+    Delimited(char),
+    Gnuplot,
+}
 
-    let file = File::open(filename)?;
-    let reader = BufReader::new(file);
+impl FileFormat {
+    /// Picks a format by the file extension, or else by the start of the file's contents
+    pub(crate) fn detect(filename: &str, start: &[u8]) -> FileFormat {
+        if filename.ends_with(".asc") {
+            FileFormat::CarpetAscii
+        } else if filename.ends_with(".csv") {
+            FileFormat::Delimited(',')
+        } else if filename.ends_with(".tsv") {
+            FileFormat::Delimited('\t')
+        } else {
+            // Without a name to go by, e.g. on standard input, Carpet files are known by their first line
+            if start.starts_with(b"# 1D ASCII output created by CarpetIOASCII") {
+                return FileFormat::CarpetAscii;
+            }
+            // ygraph files begin with a quoted header or time line
+            match start.iter().find(|b| !b.is_ascii_whitespace()) {
+                Some(b'"') | None => FileFormat::YGraph,
+                Some(_) => FileFormat::Gnuplot,
+            }
+        }
+    }
+}
+
+/// Reads all lines of a file from its cache if that is up to date, otherwise parses the file and
+/// caches the result for the next time
+fn read_datalines_cached(filename: &str, settings: &LoadSettings) -> Result<FileContents> {
+    use crate::cache::{read_cache, write_cache, SourceKey};
+
+    if let Some(cached) = read_cache(filename, settings) {
+        return Ok(cached);
+    }
+
+    let key = SourceKey::of(filename)?;
+    let (time_line_pairs, problems) = read_datalines_from_file(filename, settings)?;
+    // Failing to write the cache, e.g. in a read-only directory, only costs time on the next load
+    let _ = write_cache(filename, key, settings, &time_line_pairs, &problems);
+    Ok((time_line_pairs, problems))
+}
 
-    let mut datalines: Vec<DataLine> = Vec::new();
-    let mut times: Vec<f64> = Vec::new();
+/// Reads all lines of a file, decompressing it first if needed, along with any problems found in it
+fn read_datalines_from_file(filename: &str, settings: &LoadSettings) -> Result<FileContents> {
+    use crate::compression::{open_file, strip_compression_extension};
+
+    let (_, mut reader) = open_file(filename)?;
+
+    // Peek at the beginning of the file without consuming it
+    let format = FileFormat::detect(strip_compression_extension(filename), reader.fill_buf()?);
+
+    let mut problems = LineProblems::new();
+    let mut selector = StepSelector::new(settings.time_filter, settings.time_tolerance);
+    let time_line_pairs = read_datalines(reader, format, filename, settings, &mut selector, &mut problems)?;
+    Ok((time_line_pairs, problems))
+}
+
+/// Reads the lines of the blocks the selector keeps. Points of other blocks are not stored.
+pub(crate) fn read_datalines<R: BufRead>(reader: R, format: FileFormat, filename: &str, settings: &LoadSettings,
+                                         selector: &mut StepSelector, problems: &mut LineProblems) -> Result<Vec<(Time, DataLine)>> {
+    match format {
+        FileFormat::YGraph => read_ygraph_datalines(reader, &settings.columns, filename, selector, problems),
+        FileFormat::CarpetAscii => {
+            use crate::carpet::{axis_from_filename, read_carpet_datalines};
+            read_carpet_datalines(reader, &settings.columns, axis_from_filename(filename), selector, problems)
+        },
+        FileFormat::Delimited(delimiter) => {
+            crate::delimited::read_delimited_datalines(reader, delimiter, &settings.columns, selector, problems)
+        },
+        FileFormat::Gnuplot => crate::gnuplot::read_gnuplot_datalines(reader, &settings.columns, filename, selector, problems),
+    }
+}
+
+fn read_ygraph_datalines<R: BufRead>(reader: R, columns: &ColumnSelection, filename: &str,
+                                     selector: &mut StepSelector, problems: &mut LineProblems) -> Result<Vec<(Time, DataLine)>> {
+    let columns = PlainColumns::resolve(columns);
+    let mut time_line_pairs: Vec<(Time, DataLine)> = Vec::new();
+    let mut lines: Vec<DataLine> = columns.lines_of_columns(&None, filename);
+    let mut skipping_block = false; // after a bad time header or one not selected, until the next one
+    let mut block_start = 0; // line number of the latest time header
+    let mut line_number = 0;
 
     for l in reader.lines() {
         let line = l?;
+        line_number += 1;
         if line.is_empty() {
             continue;
         }
         if line.starts_with('\"') {
-            if line.starts_with("\"Time = ") {
-                let time_str = line.get(8..).unwrap();
-                let time = time_str.trim_matches('"').parse::<f64>().unwrap();
-                times.push(time);
-                // Begin a new dataline
-                datalines.push(DataLine::new());
-            }
-          // Add points to the latest dataline  
-        } else if let Some(dataline) = datalines.last_mut() {
-            let words: Vec<&str> = line.trim().split_whitespace().collect();
-            if words.len() >= 2 { // ignore malformed lines, e.g. caused by a program being stopped mid-write
-                if let (Ok(x), Ok(y)) = (words[0].parse::<f64>(),
-                                         words[1].parse::<f64>()) {
-                    if !x.is_nan() && !y.is_nan() {
-                        dataline.push((x,y));
-                    }
+            if let Some(time_str) = line.strip_prefix("\"Time = ") {
+                let time_str = time_str.trim_matches('"');
+                match parse_time(time_str) {
+                    Some(time) if !selector.keeps(time) => {
+                        skipping_block = true;
+                        block_start = line_number;
+                    },
+                    Some(time) => {
+                        // Begin a new dataline for each y column
+                        for line in &lines {
+                            time_line_pairs.push((time, line.clone()));
+                        }
+                        skipping_block = false;
+                        block_start = line_number;
+                    },
+                    None => {
+                        // Points up to the next time header have no time to go with
+                        problems.push((line_number, ReadProblem::BadTime(time_str.to_string())));
+                        skipping_block = true;
+                    },
                 }
+            } else if line.starts_with("\"Output of ") {
+                // Header applies to all following datalines
+                lines = columns.lines_of_columns(&LineMetadata::from_header(&line), filename);
+            }
+          // Add points to the latest datalines
+        } else if skipping_block || time_line_pairs.is_empty() {
+            continue;
+        } else {
+            match columns.parse_row(&line) {
+                Some(row) => columns.push_row(row, &mut time_line_pairs),
+                // e.g. caused by a program being stopped mid-write
+                None => problems.push((line_number, ReadProblem::MalformedLine)),
             }
         }
     }
-
-    // After reading all datalines, zip them together with their time values
-    let mut time_line_pairs: Vec<(Time, DataLine)> = Vec::new();
-    for (t, l) in times.iter().zip(datalines) {
-        time_line_pairs.push((*t, l));
+    mark_truncated(problems, line_number);
+    // A time header without any points after it, at the end of the file
+    if !skipping_block && matches!(time_line_pairs.last(), Some((_, l)) if l.points.is_empty()) {
+        problems.push((block_start, ReadProblem::TruncatedBlock));
     }
 
     Ok(time_line_pairs)
-}
\ No newline at end of file
+}
+
+/// Finds the time blocks of a ygraph file, each beginning with a time line
+pub fn index_ygraph_blocks<R: BufRead>(reader: R, problems: &mut LineProblems) -> Result<Vec<BlockIndex>> {
+    let mut blocks = Vec::new();
+    let mut metadata: Option<LineMetadata> = None;
+
+    for (i, l) in crate::lazy::lines_with_offsets(reader).enumerate() {
+        let (offset, line) = l?;
+        if let Some(time_str) = line.strip_prefix("\"Time = ") {
+            let time_str = time_str.trim_matches('"');
+            match parse_time(time_str) {
+                Some(time) => blocks.push(BlockIndex {
+                    time,
+                    start: offset,
+                    metadata: metadata.clone(),
+                }),
+                None => problems.push((i + 1, ReadProblem::BadTime(time_str.to_string()))),
+            }
+        } else if line.starts_with("\"Output of ") {
+            metadata = LineMetadata::from_header(&line);
+        }
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory for the files of a test
+    fn test_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("muninn-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    /// Writes a headerless ygraph file with a line at each of the given times
+    fn write_ygraph(path: &std::path::Path, times: &[Time]) -> String {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let blocks: Vec<String> = times.iter().map(|t| format!("\"Time = {}\n0 {}\n1 {}\n\n", t, t, 2.0 * t)).collect();
+        std::fs::write(path, blocks.concat()).unwrap();
+        path.display().to_string()
+    }
+
+    #[test]
+    fn runs_of_the_same_start_in_different_directories_are_both_kept() {
+        let directory = test_directory("same-start");
+        let sim_a = write_ygraph(&directory.join("sim_a/rho.xg"), &[0.0, 1.0, 2.0]);
+        let sim_b = write_ygraph(&directory.join("sim_b/rho.xg"), &[0.0, 1.0, 2.0]);
+        let settings = LoadSettings::new();
+
+        // Loaded together
+        let (data, report) = Data::from_files(vec![sim_a.clone(), sim_b.clone()], &settings);
+        let data = data.unwrap();
+        assert!(report.restarts.is_empty());
+        assert!(data.series[0].dataslices.iter().all(|s| s.datalines.len() == 2));
+
+        // Loaded one after the other
+        let (mut data, _) = Data::from_files(vec![sim_a], &settings);
+        let (added, _) = Data::from_files(vec![sim_b], &settings);
+        let overlaps = data.as_mut().unwrap().merge(added.unwrap(), &settings);
+        assert!(overlaps.is_empty());
+        assert!(data.unwrap().series[0].dataslices.iter().all(|s| s.datalines.len() == 2));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn restarted_run_keeps_the_newer_segment() {
+        let directory = test_directory("restart");
+        let older = write_ygraph(&directory.join("output-0000/rho.xg"), &[0.0, 1.0, 2.0]);
+        let newer = write_ygraph(&directory.join("output-0001/rho.xg"), &[1.0, 2.0, 3.0]);
+
+        // Unless asked for, both are kept
+        let (data, report) = Data::from_files(vec![older.clone(), newer.clone()], &LoadSettings::new());
+        assert!(report.restarts.is_empty());
+        assert!(data.unwrap().series[0].dataslices.iter().any(|s| s.datalines.len() == 2));
+
+        let settings = LoadSettings { merge_restarts: true, ..LoadSettings::new() };
+        let (data, report) = Data::from_files(vec![older, newer], &settings);
+        let data = data.unwrap();
+        assert_eq!(report.restarts.len(), 1);
+        assert_eq!(report.restarts[0].dropped_lines, 2);
+        assert_eq!(data.times(), vec![0.0, 1.0, 2.0, 3.0]);
+        assert!(data.series[0].dataslices.iter().all(|s| s.datalines.len() == 1));
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    /// A block of Carpet 1D output of rho and press at a time, without its own header
+    fn carpet_block(time: Time) -> String {
+        let rows: Vec<String> = (0..3).map(|i| format!("0 0 0 0 0 {} 0 0 {} {} 0 0 {} {}\n", i, time, i, time + i as f64, 1.0)).collect();
+        format!("{}\n", rows.concat())
+    }
+
+    #[test]
+    fn appended_carpet_blocks_keep_the_columns_of_the_header() {
+        use std::io::Write;
+
+        let directory = test_directory("follow-carpet");
+        let path = directory.join("rho.x.asc");
+        let header = "# 1D ASCII output created by CarpetIOASCII\n\
+                      # column format: 1:it 2:tl 3:rl 4:c 5:ml 6:ix 7:iy 8:iz 9:time 10:x 11:y 12:z 13:data\n\
+                      # data columns: 13:rho 14:press\n";
+        std::fs::write(&path, format!("{}{}", header, carpet_block(0.0))).unwrap();
+        let settings = LoadSettings::new();
+
+        let (data, _) = Data::from_files(vec![path.display().to_string()], &settings);
+        let mut data = data.unwrap();
+        let names: Vec<String> = data.series.iter().map(|s| s.name.clone()).collect();
+        // Nothing is read for following until the file grows
+        assert!(data.sources.iter().all(|s| s.complete_length.is_none()));
+
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(carpet_block(1.0).as_bytes()).unwrap();
+        assert!(data.update_from_files(&settings));
+        assert_eq!(data.series.iter().map(|s| s.name.clone()).collect::<Vec<String>>(), names);
+        assert_eq!(data.times(), vec![0.0, 1.0]);
+        let last_slice = data.series[0].dataslices.last().unwrap();
+        assert_eq!(last_slice.datalines[0].points, vec![(0.0, 1.0), (1.0, 2.0), (2.0, 3.0)]);
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn ygraph_blocks_without_a_finite_time_are_skipped() {
+        let text = "\"Time = 1\n0 1\n\n\"Time = nan\n0 2\n\n\"Time = inf\n0 3\n\n\"Time = 0\n0 4\n";
+        let mut problems = LineProblems::new();
+        let columns = LoadSettings::new().columns;
+        let pairs = read_ygraph_datalines(text.as_bytes(), &columns, "rho.xg", &mut StepSelector::all(), &mut problems).unwrap();
+        assert_eq!(pairs.iter().map(|(t, l)| (*t, l.points.clone())).collect::<Vec<_>>(), vec![(1.0, vec![(0.0, 1.0)]), (0.0, vec![(0.0, 4.0)])]);
+        assert_eq!(problems, vec![(4, ReadProblem::BadTime("nan".to_string())), (7, ReadProblem::BadTime("inf".to_string()))]);
+
+        let series = Series::from_time_line_pairs("rho".to_string(), pairs, &TimeTolerance::new());
+        assert_eq!(series.dataslices.iter().map(|s| s.time).collect::<Vec<Time>>(), vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn points_with_missing_channel_values_are_left_out() {
+        let mut columns = LoadSettings::new().columns;
+        columns.imaginary = vec![Column::Number(3)];
+        let text = "\"Time = 0\n0 1 2\n1 2 nan\n2 3 4\n";
+        let pairs = read_ygraph_datalines(text.as_bytes(), &columns, "psi.xg", &mut StepSelector::all(), &mut LineProblems::new()).unwrap();
+        assert_eq!(pairs[0].1.points, vec![(0.0, 1.0), (2.0, 3.0)]);
+        assert_eq!(pairs[0].1.channel(ChannelKind::Imaginary), Some(&[2.0, 4.0][..]));
+    }
+
+    #[test]
+    fn plain_channels_go_with_their_y_column_when_another_is_missing() {
+        let mut columns = LoadSettings::new().columns;
+        // Plain files have no column names
+        columns.y = vec![Column::Name("rho".to_string()), Column::Number(2)];
+        columns.imaginary = vec![Column::Number(4), Column::Number(3)];
+        let plain = PlainColumns::resolve(&columns);
+        assert_eq!(plain.y, vec![1]);
+        assert_eq!(plain.channels, vec![vec![(ChannelKind::Imaginary, 2)]]);
+    }
+
+    /// The times a selector keeps out of the given ones
+    fn kept(filter: TimeFilter, tolerance: TimeTolerance, times: &[Time]) -> Vec<Time> {
+        let mut selector = StepSelector::new(filter, tolerance);
+        times.iter().copied().filter(|t| selector.keeps(*t)).collect()
+    }
+
+    #[test]
+    fn stride_counts_steps_of_matching_times() {
+        let filter = TimeFilter { stride: 2, ..TimeFilter::new() };
+        let tolerance = TimeTolerance { absolute: 0.001, ..TimeTolerance::new() };
+        // Lines of the same step can differ slightly in time, e.g. between refinement levels
+        let times = [0.0, 0.0001, 1.0, 1.0001, 2.0, 3.0, 4.0, 4.0001];
+        assert_eq!(kept(filter, tolerance, &times), vec![0.0, 0.0001, 2.0, 4.0, 4.0001]);
+    }
+
+    #[test]
+    fn window_includes_its_bounds() {
+        let filter = TimeFilter { start: Some(1.0), end: Some(3.0), ..TimeFilter::new() };
+        let times = [0.0, 1.0, 2.0, 3.0, 4.0];
+        assert_eq!(kept(filter, TimeTolerance::new(), &times), vec![1.0, 2.0, 3.0]);
+
+        // Including times that only match the bounds within the tolerance
+        let tolerance = TimeTolerance { absolute: 0.001, ..TimeTolerance::new() };
+        let times = [0.9995, 2.0, 3.0005, 3.01];
+        assert_eq!(kept(filter, tolerance, &times), vec![0.9995, 2.0, 3.0005]);
+    }
+
+    #[test]
+    fn stride_counts_from_the_start_of_the_window() {
+        let filter = TimeFilter { start: Some(2.0), end: Some(8.0), stride: 3 };
+        let times: Vec<Time> = (0..=10).map(|t| t as Time).collect();
+        assert_eq!(kept(filter, TimeTolerance::new(), &times), vec![2.0, 5.0, 8.0]);
+    }
+
+    #[test]
+    fn full_header_is_parsed() {
+        let header = "\"Output of X axis, variable ana.CSI, rank 0, grid 0, type CU, orientation: CE";
+        let metadata = LineMetadata::from_header(header).unwrap();
+        assert_eq!(metadata.axis, "X");
+        assert_eq!(metadata.variable, "ana.CSI");
+        assert_eq!(metadata.rank, Some(0));
+        assert_eq!(metadata.grid, Some(0));
+        assert_eq!(metadata.output_type.as_deref(), Some("CU"));
+        assert_eq!(metadata.orientation.as_deref(), Some("CE"));
+    }
+
+    #[test]
+    fn partial_header_leaves_out_what_is_missing() {
+        let metadata = LineMetadata::from_header("\"Output of variable psi, x axis, grid 2").unwrap();
+        assert_eq!(metadata.axis, "x");
+        assert_eq!(metadata.variable, "psi");
+        assert_eq!(metadata.rank, None);
+        assert_eq!(metadata.grid, Some(2));
+        assert_eq!(metadata.output_type, None);
+        assert_eq!(metadata.orientation, None);
+
+        // Without an axis or a variable it is no use
+        assert!(LineMetadata::from_header("\"Output of variable psi").is_none());
+        assert!(LineMetadata::from_header("\"Output of X axis, rank 0").is_none());
+    }
+
+    #[test]
+    fn malformed_headers() {
+        assert!(LineMetadata::from_header("\"Time = 0").is_none());
+        assert!(LineMetadata::from_header("").is_none());
+
+        // Unknown fields and numbers that are none are passed over
+        let metadata = LineMetadata::from_header("\"Output of X axis, variable rho, rank first, level 3,, grid").unwrap();
+        assert_eq!(metadata.variable, "rho");
+        assert_eq!(metadata.rank, None);
+        assert_eq!(metadata.grid, None);
+    }
+}
@@ -0,0 +1,280 @@
+use crate::data::{mark_truncated, parse_time, ChannelKind, Column, ColumnSelection, DataLine, LineMetadata, LineProblems, ReadProblem, StepSelector, Time};
+use std::io::BufRead;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Splits a row into its fields, removing surrounding whitespace and quotes. Delimiters within quotes
+/// are part of the field, and a doubled quote within quotes stands for a quote, as in CSV.
+fn split_row(row: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = row.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            },
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            c => field.push(c),
+        }
+    }
+    fields.push(field.trim().to_string());
+    fields
+}
+
+/// Finds the index of a column in the header
+fn index_of(header: &[String], column: &Column) -> Option<usize> {
+    match column {
+        Column::Number(number) => number.checked_sub(1).filter(|i| *i < header.len()),
+        Column::Name(name) => header.iter().position(|h| h == name),
+    }
+}
+
+/// Reads the lines of a delimited text file (CSV, TSV) with an optional header row. Consecutive rows
+/// with the same time form one dataline for each y column. Unless selected otherwise, the time is read
+/// from a column named "time" or "t", x is the first other column, and all remaining columns that are
+/// not read as imaginary parts or uncertainties are y.
+/// Without a time column, all rows belong to a single timestep at t = 0. Selected columns that the
+/// file does not have are reported as problems of its first row, and nothing is read without the
+/// selected time or x column.
+pub fn read_delimited_datalines<R: BufRead>(reader: R, delimiter: char, columns: &ColumnSelection,
+                                            selector: &mut StepSelector, problems: &mut LineProblems) -> Result<Vec<(Time, DataLine)>> {
+    let mut rows = Vec::new(); // with their line numbers
+    let mut line_number = 0;
+    for l in reader.lines() {
+        let line = l?;
+        line_number += 1;
+        let line = line.trim();
+        // skip empty lines and comments
+        if !line.is_empty() && !line.starts_with('#') {
+            rows.push((line_number, line.to_string()));
+        }
+    }
+
+    let (first_row_number, first_row) = match rows.first() {
+        Some((number, row)) => (*number, split_row(row, delimiter)),
+        None => return Ok(Vec::new()),
+    };
+
+    // A first row that is not entirely numeric is taken as the header
+    let has_header = first_row.iter().any(|field| field.parse::<f64>().is_err());
+    let header: Vec<String> = if has_header {
+        first_row.clone()
+    } else {
+        (1..=first_row.len()).map(|i| format!("column {}", i)).collect()
+    };
+
+    let missing: Vec<&Column> = columns.time.iter().chain(&columns.x).chain(&columns.y).chain(columns.channel_columns())
+        .filter(|c| index_of(&header, c).is_none())
+        .collect();
+    for column in &missing {
+        problems.push((first_row_number, ReadProblem::MissingColumn(column.to_string())));
+    }
+    let is_missing = |column: &Option<Column>| matches!(column, Some(c) if missing.contains(&c));
+    if is_missing(&columns.time) || is_missing(&columns.x) {
+        return Ok(Vec::new());
+    }
+
+    let time_index = match &columns.time {
+        Some(column) => index_of(&header, column),
+        None => header.iter().position(|h| h.eq_ignore_ascii_case("time") || h.eq_ignore_ascii_case("t")),
+    };
+    let x_index = match &columns.x {
+        Some(column) => index_of(&header, column),
+        None => (0..header.len()).find(|i| Some(*i) != time_index),
+    };
+    let x_index = match x_index {
+        Some(index) => index,
+        None => return Ok(Vec::new()),
+    };
+    let channel_indices: Vec<usize> = columns.channel_columns().filter_map(|c| index_of(&header, c)).collect();
+    // The index of each y column, with its position among the selected ones, which its channels go by
+    let y_columns: Vec<(usize, usize)> = if columns.y.is_empty() {
+        (0..header.len())
+            .filter(|i| Some(*i) != time_index && *i != x_index && !channel_indices.contains(i))
+            .enumerate()
+            .collect()
+    } else {
+        columns.y.iter().enumerate()
+            .filter_map(|(position, c)| index_of(&header, c).map(|index| (position, index)))
+            .collect()
+    };
+    let y_indices: Vec<usize> = y_columns.iter().map(|(_, index)| *index).collect();
+    // The channels of each y column, with their indices
+    let channels: Vec<Vec<(ChannelKind, usize)>> = y_columns.iter().map(|(position, _)| {
+        columns.channels_of_y(*position).into_iter()
+            .filter_map(|(kind, column)| index_of(&header, column).map(|index| (kind, index)))
+            .collect()
+    }).collect();
+
+    let mut time_line_pairs: Vec<(Time, DataLine)> = Vec::new();
+    let mut current_time: Option<Time> = None;
+
+    for (row_number, row) in rows.iter().skip(if has_header { 1 } else { 0 }) {
+        let fields = split_row(row, delimiter);
+        let malformed = (*row_number, ReadProblem::MalformedLine);
+        let value = |index: usize| fields.get(index).and_then(|f| f.parse::<f64>().ok());
+
+        let time = match time_index {
+            Some(index) => match fields.get(index).and_then(|f| parse_time(f)) {
+                Some(t) => t,
+                None => {
+                    problems.push(malformed);
+                    continue;
+                },
+            },
+            None => 0.0,
+        };
+        if !selector.keeps(time) {
+            continue;
+        }
+        let x = match value(x_index) {
+            Some(x) if !x.is_nan() => x,
+            Some(_) => continue,
+            None => {
+                problems.push(malformed);
+                continue;
+            },
+        };
+
+        // Begin new datalines, one for each y column, whenever the time changes
+        if current_time != Some(time) {
+            for (y_index, channels) in y_indices.iter().zip(&channels) {
+                let metadata = LineMetadata {
+                    axis: header[x_index].clone(),
+                    variable: header[*y_index].clone(),
+                    rank: None,
+                    grid: None,
+                    output_type: None,
+                    orientation: None,
+                    refinement_level: None,
+                    component: None,
+                    series: None,
+                };
+                let kinds: Vec<ChannelKind> = channels.iter().map(|(kind, _)| *kind).collect();
+                time_line_pairs.push((time, DataLine::new(Some(metadata), &kinds)));
+            }
+            current_time = Some(time);
+        }
+
+        // Lines of this time are the last ones added, in the order of the y columns
+        let first_line = time_line_pairs.len() - y_indices.len();
+        for (i, (y_index, channels)) in y_indices.iter().zip(&channels).enumerate() {
+            let channel_values = channels.iter().map(|(_, index)| value(*index)).collect::<Option<Vec<f64>>>();
+            match (value(*y_index), channel_values) {
+                (Some(y), Some(channel_values)) if !y.is_nan() && !channel_values.iter().any(|v| v.is_nan()) => {
+                    time_line_pairs[first_line + i].1.push((x, y), &channel_values);
+                },
+                _ => {},
+            }
+        }
+    }
+    mark_truncated(problems, line_number);
+
+    Ok(time_line_pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::LoadSettings;
+
+    fn read(text: &str, columns: &ColumnSelection) -> (Vec<(Time, DataLine)>, LineProblems) {
+        let mut problems = LineProblems::new();
+        let time_line_pairs = read_delimited_datalines(text.as_bytes(), ',', columns, &mut StepSelector::all(), &mut problems).unwrap();
+        (time_line_pairs, problems)
+    }
+
+    fn variables(time_line_pairs: &[(Time, DataLine)]) -> Vec<String> {
+        time_line_pairs.iter().map(|(_, l)| l.metadata.as_ref().unwrap().variable.clone()).collect()
+    }
+
+    #[test]
+    fn first_row_with_names_is_the_header() {
+        let columns = LoadSettings::new().columns;
+        let (pairs, problems) = read("time,x,rho,press\n0,0,1,2\n0,1,3,4\n1,0,5,6\n", &columns);
+        assert!(problems.is_empty());
+        assert_eq!(pairs.iter().map(|(t, _)| *t).collect::<Vec<Time>>(), vec![0.0, 0.0, 1.0, 1.0]);
+        assert_eq!(variables(&pairs), vec!["rho", "press", "rho", "press"]);
+        assert_eq!(pairs[0].1.metadata.as_ref().unwrap().axis, "x");
+        assert_eq!(pairs[0].1.points, vec![(0.0, 1.0), (1.0, 3.0)]);
+    }
+
+    #[test]
+    fn numeric_first_row_is_data() {
+        let columns = LoadSettings::new().columns;
+        let (pairs, problems) = read("0,1,2\n1,3,4\n", &columns);
+        assert!(problems.is_empty());
+        assert_eq!(variables(&pairs), vec!["column 2", "column 3"]);
+        assert_eq!(pairs[0].0, 0.0);
+        assert_eq!(pairs[0].1.points, vec![(0.0, 1.0), (1.0, 3.0)]);
+    }
+
+    #[test]
+    fn quoted_fields_may_contain_the_delimiter() {
+        assert_eq!(split_row(" \"a, b\" , 1,\"say \"\"hi\"\"\"", ','), vec!["a, b", "1", "say \"hi\""]);
+
+        let columns = LoadSettings::new().columns;
+        let (pairs, problems) = read("x,\"rho, density\"\n0,1\n", &columns);
+        assert!(problems.is_empty());
+        assert_eq!(variables(&pairs), vec!["rho, density"]);
+    }
+
+    #[test]
+    fn selected_columns_missing_from_the_header_are_reported() {
+        let text = "# comment\ntime,x,rho\n0,0,1\n";
+
+        let mut columns = LoadSettings::new().columns;
+        columns.time = Some(Column::Name("tme".to_string()));
+        let (pairs, problems) = read(text, &columns);
+        assert!(pairs.is_empty());
+        assert_eq!(problems, vec![(2, ReadProblem::MissingColumn("tme".to_string()))]);
+
+        let mut columns = LoadSettings::new().columns;
+        columns.x = Some(Column::Number(7));
+        let (pairs, problems) = read(text, &columns);
+        assert!(pairs.is_empty());
+        assert_eq!(problems, vec![(2, ReadProblem::MissingColumn("7".to_string()))]);
+
+        // Other columns are still read without a missing y column
+        let mut columns = LoadSettings::new().columns;
+        columns.y = vec![Column::Name("rho".to_string()), Column::Name("press".to_string())];
+        let (pairs, problems) = read(text, &columns);
+        assert_eq!(variables(&pairs), vec!["rho"]);
+        assert_eq!(problems, vec![(2, ReadProblem::MissingColumn("press".to_string()))]);
+    }
+
+    #[test]
+    fn points_with_missing_channel_values_are_left_out() {
+        let mut columns = LoadSettings::new().columns;
+        columns.y = vec![Column::Name("re".to_string())];
+        columns.imaginary = vec![Column::Name("im".to_string())];
+        let (pairs, problems) = read("x,re,im\n0,1,nan\n1,2,3\n", &columns);
+        assert!(problems.is_empty());
+        assert_eq!(pairs[0].1.points, vec![(1.0, 2.0)]);
+        assert_eq!(pairs[0].1.channel(ChannelKind::Imaginary), Some(&[3.0][..]));
+    }
+
+    #[test]
+    fn channels_go_with_their_y_column_when_another_is_missing() {
+        let mut columns = LoadSettings::new().columns;
+        columns.y = vec![Column::Name("press".to_string()), Column::Name("rho".to_string())];
+        columns.uncertainty = vec![Column::Name("dpress".to_string()), Column::Name("drho".to_string())];
+        let (pairs, problems) = read("x,rho,drho\n0,1,0.5\n", &columns);
+        assert_eq!(problems, vec![(1, ReadProblem::MissingColumn("press".to_string())), (1, ReadProblem::MissingColumn("dpress".to_string()))]);
+        assert_eq!(variables(&pairs), vec!["rho"]);
+        assert_eq!(pairs[0].1.channel(ChannelKind::Uncertainty), Some(&[0.5][..]));
+    }
+
+    #[test]
+    fn rows_without_a_finite_time_are_malformed() {
+        let columns = LoadSettings::new().columns;
+        let (pairs, problems) = read("t,x,rho\nnan,0,1\n1,0,2\n", &columns);
+        assert_eq!(problems, vec![(2, ReadProblem::MalformedLine)]);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].0, pairs[0].1.points.clone()), (1.0, vec![(0.0, 2.0)]));
+    }
+}
@@ -0,0 +1,271 @@
+use crate::data::{mark_truncated, parse_time, Column, ColumnSelection, DataLine, LineMetadata, LineProblems, PlainColumns, ReadProblem, StepSelector, Time};
+use crate::lazy::{lines_with_offsets, BlockIndex};
+use std::io::BufRead;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Splits a comment like "# time = 1.5" or "# Time: 1.5" into a lowercase key and a value
+fn parse_comment(comment: &str) -> Option<(String, String)> {
+    let comment = comment.trim_start_matches('#').trim();
+    let (key, value) = comment.split_once(['=', ':'])?;
+    Some((key.trim().to_lowercase(), value.trim().to_string()))
+}
+
+/// Reads a file of gnuplot-style data blocks, as written by the gnuplot export. Each block of
+/// points, separated by blank lines, becomes one dataline. Comments of the form "# time = ..."
+/// set the time of the following blocks (0 until the first such comment), and blocks after a time
+/// that is not a finite number are skipped. Other "# key = value" comments describe their
+/// metadata. A "# series = ..." comment starts over with fresh metadata, and names the series of
+/// the following lines, as written by the export. A "# columns = x y ..." comment tells which
+/// columns are channels, unless columns were selected. With several y columns, each block becomes
+/// one dataline for each of them.
+pub fn read_gnuplot_datalines<R: BufRead>(reader: R, columns: &ColumnSelection, filename: &str,
+                                          selector: &mut StepSelector, problems: &mut LineProblems) -> Result<Vec<(Time, DataLine)>> {
+    let selected_columns = PlainColumns::resolve(columns);
+    let mut announced_columns: Option<PlainColumns> = None;
+    let mut time_line_pairs: Vec<(Time, DataLine)> = Vec::new();
+
+    let mut time: Time = 0.0;
+    let mut comments: Vec<(String, String)> = Vec::new();
+    let mut block_finished = true;
+    let mut skipping_block = false; // after a bad time, until the next one
+    let mut line_number = 0;
+
+    for l in reader.lines() {
+        let line = l?;
+        line_number += 1;
+        let line = line.trim();
+        if line.is_empty() {
+            block_finished = true;
+            continue;
+        }
+        if line.starts_with('#') {
+            block_finished = true;
+            if let Some((key, value)) = parse_comment(line) {
+                match key.as_str() {
+                    "time" | "t" => match parse_time(&value) {
+                        Some(t) => {
+                            time = t;
+                            skipping_block = false;
+                        },
+                        None => {
+                            problems.push((line_number, ReadProblem::BadTime(value)));
+                            skipping_block = true;
+                        },
+                    },
+                    "series" => {
+                        comments = vec![(key, value)];
+                        announced_columns = None;
+                    },
+                    "columns" => announced_columns = columns_of_comment(&value, columns),
+                    _ => comments.push((key, value)),
+                }
+            }
+            continue;
+        }
+        if skipping_block {
+            continue;
+        }
+
+        let columns = announced_columns.as_ref().unwrap_or(&selected_columns);
+        let row = match columns.parse_row(line) {
+            Some(row) => row,
+            None => {
+                problems.push((line_number, ReadProblem::MalformedLine));
+                continue;
+            },
+        };
+        if !selector.keeps(time) {
+            continue;
+        }
+        // Begin new datalines with the first point after a break
+        if block_finished {
+            for line in columns.lines_of_columns(&LineMetadata::from_comments(&comments), filename) {
+                time_line_pairs.push((time, line));
+            }
+            block_finished = false;
+        }
+        columns.push_row(row, &mut time_line_pairs);
+    }
+    mark_truncated(problems, line_number);
+
+    Ok(time_line_pairs)
+}
+
+/// The columns announced by a comment like "# columns = x y imaginary uncertainty", as written by
+/// the export. Returns None if columns were selected, which take precedence, or if the comment
+/// lacks x or y.
+fn columns_of_comment(comment: &str, selection: &ColumnSelection) -> Option<PlainColumns> {
+    if selection.x.is_some() || !selection.y.is_empty() || selection.channel_columns().next().is_some() {
+        return None;
+    }
+    let names: Vec<String> = comment.split_whitespace().map(|name| name.to_lowercase()).collect();
+    let column_of = |name: &str| names.iter().position(|n| n == name).map(|i| Column::Number(i + 1));
+    let announced = ColumnSelection {
+        time: None,
+        x: Some(column_of("x")?),
+        y: vec![column_of("y")?],
+        imaginary: column_of("imaginary").into_iter().collect(),
+        uncertainty: column_of("uncertainty").into_iter().collect(),
+    };
+    Some(PlainColumns::resolve(&announced))
+}
+
+/// Finds the blocks of a gnuplot-style file, each beginning with a "# time = ..." comment. Points
+/// before the first such comment form a block at t = 0.
+pub fn index_gnuplot_blocks<R: BufRead>(reader: R, problems: &mut LineProblems) -> Result<Vec<BlockIndex>> {
+    let mut blocks: Vec<BlockIndex> = Vec::new();
+    let mut comments: Vec<(String, String)> = Vec::new();
+    let mut in_header = true; // between the comment beginning a block and its first point
+
+    for (i, l) in lines_with_offsets(reader).enumerate() {
+        let (offset, line) = l?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            if let Some((key, value)) = parse_comment(line) {
+                match key.as_str() {
+                    "time" | "t" => match parse_time(&value) {
+                        Some(time) => {
+                            blocks.push(BlockIndex {
+                                time,
+                                start: offset,
+                                metadata: None,
+                            });
+                            in_header = true;
+                        },
+                        None => problems.push((i + 1, ReadProblem::BadTime(value))),
+                    },
+                    "series" => comments = vec![(key, value)],
+                    _ => comments.push((key, value)),
+                }
+            }
+            continue;
+        }
+
+        if blocks.is_empty() {
+            blocks.push(BlockIndex {
+                time: 0.0,
+                start: 0,
+                metadata: None,
+            });
+        }
+        // The comments up to the first point describe the block
+        if in_header {
+            if let Some(block) = blocks.last_mut() {
+                block.metadata = LineMetadata::from_comments(&comments);
+            }
+            in_header = false;
+        }
+    }
+
+    Ok(blocks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{series_name_of_line, to_string_gnuplot, DataSlice, SeriesSlice};
+    use std::sync::Arc;
+
+    fn series_slice(name: &str, metadata: Option<LineMetadata>, points: &[(f64, f64)]) -> SeriesSlice {
+        let mut line = DataLine::new(metadata, &[]);
+        line.points = points.to_vec();
+        SeriesSlice {
+            name: name.to_string(),
+            slice: Arc::new(DataSlice {
+                time: 1.5,
+                datalines: vec![line],
+            }),
+        }
+    }
+
+    #[test]
+    fn exported_series_are_read_back_under_their_names() {
+        let metadata = LineMetadata::from_header("\"Output of variable psi, x axis").unwrap();
+        let exported = vec![
+            series_slice("rho", None, &[(0.0, 1.0), (1.0, 2.0)]),
+            series_slice(&metadata.series_name(), Some(metadata.clone()), &[(0.0, 3.0)]),
+            series_slice("rho (maximum)", None, &[(0.0, 4.0)]),
+        ];
+        let text = to_string_gnuplot(&exported);
+
+        let columns = crate::data::LoadSettings::new().columns;
+        let pairs = read_gnuplot_datalines(text.as_bytes(), &columns, "export.gnuplot",
+                                           &mut StepSelector::all(), &mut LineProblems::new()).unwrap();
+        assert_eq!(pairs.len(), exported.len());
+        for ((time, line), series_slice) in pairs.iter().zip(&exported) {
+            assert_eq!(*time, 1.5);
+            assert_eq!(series_name_of_line(line, "export.gnuplot"), series_slice.name);
+            assert_eq!(line.points, series_slice.slice.datalines[0].points);
+        }
+        assert_eq!(pairs[1].1.metadata.as_ref().unwrap().variable, metadata.variable);
+    }
+
+    #[test]
+    fn exported_series_read_on_demand_keep_their_names() {
+        use crate::data::{Data, LoadSettings};
+
+        let metadata = LineMetadata::from_header("\"Output of variable psi, x axis").unwrap();
+        let exported = vec![
+            series_slice("rho", None, &[(0.0, 1.0), (1.0, 2.0)]),
+            series_slice("psi (maximum)", Some(metadata), &[(0.0, 3.0)]),
+        ];
+        let path = std::env::temp_dir().join(format!("muninn-export-{}.gnuplot", std::process::id()));
+        std::fs::write(&path, to_string_gnuplot(&exported)).unwrap();
+        let mut settings = LoadSettings::new();
+        settings.on_demand = true;
+
+        let (data, _) = Data::from_files(vec![path.display().to_string()], &settings);
+        let mut names: Vec<String> = data.unwrap().on_demand.unwrap().at_time(1.5).iter()
+            .flat_map(|s| s.slice.datalines.iter().map(move |l| (s.name.clone(), series_name_of_line(l, ""))))
+            .map(|(name, line_name)| {
+                assert_eq!(name, line_name);
+                name
+            })
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["psi (maximum)", "rho"]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn exported_channels_are_read_back() {
+        use crate::data::{Channel, ChannelKind};
+
+        let mut line = DataLine::new(None, &[]);
+        line.points = vec![(0.0, 1.0), (1.0, 2.0)];
+        line.channels = vec![
+            Channel { kind: ChannelKind::Imaginary, values: vec![0.5, -0.5] },
+            Channel { kind: ChannelKind::Uncertainty, values: vec![0.1, 0.2] },
+        ];
+        let slice = DataSlice {
+            time: 0.0,
+            datalines: vec![line.clone(), DataLine { channels: Vec::new(), ..line.clone() }],
+        };
+
+        let text = to_string_gnuplot(&[SeriesSlice { name: "psi".to_string(), slice: Arc::new(slice) }]);
+        let columns = crate::data::LoadSettings::new().columns;
+        let pairs = read_gnuplot_datalines(text.as_bytes(), &columns, "export.gnuplot",
+                                           &mut StepSelector::all(), &mut LineProblems::new()).unwrap();
+        assert_eq!(pairs[0].1.points, line.points);
+        assert_eq!(pairs[0].1.channels, line.channels);
+        // Each line announces its own columns
+        assert_eq!(pairs[1].1.points, line.points);
+        assert!(pairs[1].1.channels.is_empty());
+    }
+
+    #[test]
+    fn blocks_after_a_time_that_is_not_finite_are_skipped() {
+        let text = "# time = nan\n0 1\n\n# time = 2\n0 2\n";
+        let mut problems = LineProblems::new();
+        let columns = crate::data::LoadSettings::new().columns;
+        let pairs = read_gnuplot_datalines(text.as_bytes(), &columns, "export.gnuplot", &mut StepSelector::all(), &mut problems).unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].0, pairs[0].1.points.clone()), (2.0, vec![(0.0, 2.0)]));
+        assert_eq!(problems, vec![(1, ReadProblem::BadTime("nan".to_string()))]);
+    }
+}
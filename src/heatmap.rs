@@ -0,0 +1,300 @@
+use crate::contour::contour_lines;
+use crate::data::Point;
+use crate::plane::{Patch, PlaneSeriesSlice};
+use crate::plotting::{truncate_line, PlotRange, PlotSettings, Range, X_LABEL_AREA_SIZE, Y_LABEL_AREA_SIZE};
+
+use plotters::coord::Shift;
+use plotters::coord::ranged1d::ValueFormatter;
+use plotters::coord::types::RangedCoordf64;
+use plotters::prelude::*;
+
+/// Space reserved right of the heatmap for the colorbar and its tick labels, in pixels
+pub const COLORBAR_AREA_SIZE: u32 = 90;
+
+/// Steps of the viridis colormap, from low to high values
+const VIRIDIS: [(u8, u8, u8); 10] = [
+    (68, 1, 84), (72, 40, 120), (62, 74, 137), (49, 104, 142), (38, 130, 142),
+    (31, 158, 137), (53, 183, 121), (109, 205, 89), (180, 222, 44), (253, 231, 37),
+];
+
+/// Color of a position between 0 and 1 along the colormap
+fn colormap(position: f64) -> RGBColor {
+    let position = position.clamp(0.0, 1.0) * (VIRIDIS.len() - 1) as f64;
+    let i = (position.floor() as usize).min(VIRIDIS.len() - 2);
+    let f = position - i as f64;
+    let (low, high) = (VIRIDIS[i], VIRIDIS[i+1]);
+    let mix = |a: u8, b: u8| (a as f64 + f * (b as f64 - a as f64)).round() as u8;
+    RGBColor(mix(low.0, high.0), mix(low.1, high.1), mix(low.2, high.2))
+}
+
+/// Maps values onto the colormap, linearly or logarithmically between the ends of a range
+struct ColorScale {
+    range: Range,
+    logarithmic: bool,
+}
+
+impl ColorScale {
+    /// Position of a value along the colormap. None for values without a color, i.e. NaN, and
+    /// values that are not positive on a logarithmic scale.
+    fn position(&self, value: f64) -> Option<f64> {
+        let (min, max) = self.range;
+        if value.is_nan() || (self.logarithmic && value <= 0.0) {
+            None
+        } else if self.logarithmic {
+            Some((value.log10() - min.log10()) / (max.log10() - min.log10()))
+        } else {
+            Some((value - min) / (max - min))
+        }
+    }
+
+    /// Value at a position between 0 and 1 along the colormap
+    fn value_at(&self, position: f64) -> f64 {
+        let (min, max) = self.range;
+        if self.logarithmic {
+            10.0_f64.powf(min.log10() + position * (max.log10() - min.log10()))
+        } else {
+            min + position * (max - min)
+        }
+    }
+}
+
+/// Edges of the cells around grid coordinates, halfway between neighbours. A single coordinate
+/// gets a cell of width 1.
+fn cell_edges(coordinates: &[f64]) -> Vec<f64> {
+    let n = coordinates.len();
+    match n {
+        0 => Vec::new(),
+        1 => vec![coordinates[0] - 0.5, coordinates[0] + 0.5],
+        _ => {
+            let mut edges = vec![coordinates[0] - 0.5 * (coordinates[1] - coordinates[0])];
+            edges.extend(coordinates.windows(2).map(|pair| 0.5 * (pair[0] + pair[1])));
+            edges.push(coordinates[n-1] + 0.5 * (coordinates[n-1] - coordinates[n-2]));
+            edges
+        },
+    }
+}
+
+/// Makes a range usable for an axis: positive on a logarithmic axis, and not empty
+fn usable_range((mut min, mut max): Range, logarithmic: bool) -> Range {
+    if logarithmic && min <= 0.0 {
+        min = 1.0e-10;
+    }
+    if max <= min {
+        if logarithmic {
+            min *= 0.9;
+            max = min / 0.9 * 1.1;
+        } else {
+            min -= 0.05;
+            max = min + 0.1;
+        }
+    }
+    (min, max)
+}
+
+/// Smallest range containing all of the given edges, or 0 to 1 if there are none
+fn extent<'a>(edges: impl Iterator<Item = &'a Vec<f64>>) -> Range {
+    edges.filter(|e| !e.is_empty())
+        .fold(None, |range: Option<Range>, e| {
+            let (min, max) = (e[0], e[e.len()-1]);
+            Some(range.map_or((min, max), |(a, b)| (a.min(min), b.max(max))))
+        })
+        .unwrap_or((0.0, 1.0))
+}
+
+/// Two opposite corners of a rectangle
+type Corners = ((f64, f64), (f64, f64));
+
+/// A rectangle of the heatmap and its color
+type Cell = (Corners, RGBColor);
+
+/// A piece of a contour line and its color
+type Contour = (Vec<Point>, RGBColor);
+
+/// The extent of a patch, as its x and y ranges
+fn patch_extent(patch: &Patch) -> Option<(Range, Range)> {
+    match (patch.xs.first(), patch.xs.last(), patch.ys.first(), patch.ys.last()) {
+        (Some(xmin), Some(xmax), Some(ymin), Some(ymax)) => Some(((*xmin, *xmax), (*ymin, *ymax))),
+        _ => None,
+    }
+}
+
+/// Draws the cells of a heatmap and then its contour lines onto a chart, beneath axes without grid lines
+fn draw_plane<'a, DB, X, Y>(chart: &mut ChartContext<'a, DB, Cartesian2d<X, Y>>, cells: &[Cell], contours: &[Contour], x_desc: &str, y_desc: &str)
+where DB: DrawingBackend + 'a,
+      X: Ranged<ValueType = f64> + ValueFormatter<f64>,
+      Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
+    chart.configure_mesh()
+        .disable_mesh()
+        .x_desc(x_desc)
+        .y_desc(y_desc)
+        .draw()
+        .expect("Failed to draw mesh");
+    chart.draw_series(cells.iter().map(|((corner, opposite), color)| Rectangle::new([*corner, *opposite], color.filled())))
+        .expect("Failed to draw heatmap");
+    for (line, color) in contours {
+        chart.draw_series(LineSeries::new(line.clone(), color))
+            .expect("Failed to draw contour line");
+    }
+}
+
+/// Draws the colorbar as bands of color stacked along its value axis
+fn draw_colorbar_bands<'a, DB, Y>(chart: &mut ChartContext<'a, DB, Cartesian2d<RangedCoordf64, Y>>, bands: &[(f64, f64, RGBColor)], variable: &str)
+where DB: DrawingBackend + 'a,
+      Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
+{
+    chart.configure_mesh()
+        .disable_mesh()
+        .disable_x_axis()
+        .y_desc(variable)
+        .draw()
+        .expect("Failed to draw colorbar axis");
+    chart.draw_series(bands.iter().map(|(low, high, color)| Rectangle::new([(0.0, *low), (1.0, *high)], color.filled())))
+        .expect("Failed to draw colorbar");
+}
+
+fn draw_colorbar<DB: DrawingBackend>(area: &DrawingArea<DB, Shift>, scale: &ColorScale, variable: &str) {
+    const N_BANDS: usize = 100;
+    let bands: Vec<(f64, f64, RGBColor)> = (0..N_BANDS).map(|k| {
+        let position = |k: usize| k as f64 / N_BANDS as f64;
+        (scale.value_at(position(k)), scale.value_at(position(k + 1)), colormap((k as f64 + 0.5) / N_BANDS as f64))
+    }).collect();
+
+    // Bottom aligned with the heatmap, labels to the right of the bar
+    let mut builder = ChartBuilder::on(area);
+    builder.margin_left(10)
+        .x_label_area_size(X_LABEL_AREA_SIZE)
+        .right_y_label_area_size(COLORBAR_AREA_SIZE - 30);
+    let (min, max) = scale.range;
+    if scale.logarithmic {
+        let mut chart = builder.build_cartesian_2d(0.0..1.0, (min..max).log_scale())
+            .expect("Failed to build colorbar");
+        draw_colorbar_bands(&mut chart, &bands, variable);
+    } else {
+        let mut chart = builder.build_cartesian_2d(0.0..1.0, min..max)
+            .expect("Failed to build colorbar");
+        draw_colorbar_bands(&mut chart, &bands, variable);
+    }
+}
+
+/// Plots the slice of a plane series as a heatmap with a colorbar and any contour lines, into an SVG
+/// file returned as a String. Colors span the values within the plotted ranges. Where patches
+/// overlap, the finest refinement level is shown.
+pub fn plot_plane_slice_to_svg(plane: &PlaneSeriesSlice, plot_settings: &PlotSettings, image_size: &(u32, u32)) -> (String, (PlotRange, PlotRange))
+{
+    // Coarser levels first, so finer ones are drawn over them
+    let mut patches: Vec<&Patch> = plane.slice.patches.iter().collect();
+    patches.sort_by_key(|p| p.refinement_level);
+    let edges: Vec<(Vec<f64>, Vec<f64>)> = patches.iter().map(|p| (cell_edges(&p.xs), cell_edges(&p.ys))).collect();
+
+    // Ranges default to the extent of all cells
+    let x_range = match plot_settings.plot_range_x {
+        PlotRange::Fixed(range) => range,
+        PlotRange::Auto => extent(edges.iter().map(|(xs, _)| xs)),
+    };
+    let y_range = match plot_settings.plot_range_y {
+        PlotRange::Fixed(range) => range,
+        PlotRange::Auto => extent(edges.iter().map(|(_, ys)| ys)),
+    };
+    let (xmin, xmax) = usable_range(x_range, plot_settings.use_logscale_x);
+    let (ymin, ymax) = usable_range(y_range, plot_settings.use_logscale_y);
+    let plotted_ranges = (PlotRange::Fixed((xmin, xmax)), PlotRange::Fixed((ymin, ymax)));
+
+    // Cells within the plotted ranges, cut off at their edges
+    let mut visible: Vec<(Corners, f64)> = Vec::new();
+    for (patch, (x_edges, y_edges)) in patches.iter().zip(&edges) {
+        for (j, y_cell) in y_edges.windows(2).enumerate() {
+            if y_cell[1] <= ymin || y_cell[0] >= ymax {
+                continue;
+            }
+            for (i, x_cell) in x_edges.windows(2).enumerate() {
+                if x_cell[1] <= xmin || x_cell[0] >= xmax {
+                    continue;
+                }
+                let corners = ((x_cell[0].max(xmin), y_cell[0].max(ymin)), (x_cell[1].min(xmax), y_cell[1].min(ymax)));
+                visible.push((corners, patch.value(i, j)));
+            }
+        }
+    }
+
+    // Colors span the values in view that can be shown
+    let logarithmic = plot_settings.use_logscale_color;
+    let color_range = visible.iter().map(|(_, value)| *value)
+        .filter(|value| value.is_finite() && (!logarithmic || *value > 0.0))
+        .fold(None, |range: Option<Range>, value| Some(range.map_or((value, value), |(a, b)| (a.min(value), b.max(value)))))
+        .unwrap_or((0.0, 1.0));
+    let scale = ColorScale {
+        range: usable_range(color_range, logarithmic),
+        logarithmic,
+    };
+
+    // Contours are traced on each patch apart from where finer patches cover it, and cut off at
+    // the plotted ranges like lines. Over the heatmap they are black, on their own they take the
+    // color of their level.
+    let mut contours: Vec<Contour> = Vec::new();
+    if let Some(levels) = &plot_settings.contour_levels {
+        let values_in_view: Vec<f64> = visible.iter().map(|(_, value)| *value).collect();
+        for level in levels.values(&values_in_view) {
+            let color = match plot_settings.draw_heatmap {
+                true => BLACK,
+                false => colormap(scale.position(level).unwrap_or(0.0)),
+            };
+            for patch in &patches {
+                let finer: Vec<(Range, Range)> = patches.iter()
+                    .filter(|p| p.refinement_level > patch.refinement_level)
+                    .filter_map(|p| patch_extent(p))
+                    .collect();
+                for line in contour_lines(patch, level, &finer) {
+                    contours.extend(truncate_line(&line, &(xmin, xmax), &(ymin, ymax)).into_iter().map(|piece| (piece, color)));
+                }
+            }
+        }
+    }
+
+    let cells: Vec<Cell> = match plot_settings.draw_heatmap {
+        true => visible.into_iter()
+            .filter_map(|(corners, value)| scale.position(value).map(|p| (corners, colormap(p))))
+            .collect(),
+        false => Vec::new(),
+    };
+
+    let x_desc = plane.metadata.axes.0.to_lowercase();
+    let y_desc = plane.metadata.axes.1.to_lowercase();
+    let x_range = xmin..xmax;
+    let y_range = ymin..ymax;
+
+    let mut svg_string = String::new();
+    {
+        let root = SVGBackend::with_string(&mut svg_string, *image_size).into_drawing_area();
+        root.fill(&WHITE).expect("Failed to fill canvas");
+        let (heatmap_area, colorbar_area) = root.split_horizontally(image_size.0.saturating_sub(COLORBAR_AREA_SIZE));
+
+        // As for lines, each combination of linear and logarithmic axes builds its own chart
+        let mut builder = ChartBuilder::on(&heatmap_area);
+        builder.x_label_area_size(X_LABEL_AREA_SIZE)
+            .y_label_area_size(Y_LABEL_AREA_SIZE);
+        match (plot_settings.use_logscale_x, plot_settings.use_logscale_y) {
+            (false, false) => {
+                let mut chart = builder.build_cartesian_2d(x_range, y_range).expect("Failed to build chart");
+                draw_plane(&mut chart, &cells, &contours, &x_desc, &y_desc);
+            },
+            (false, true) => {
+                let mut chart = builder.build_cartesian_2d(x_range, y_range.log_scale()).expect("Failed to build chart");
+                draw_plane(&mut chart, &cells, &contours, &x_desc, &y_desc);
+            },
+            (true, false) => {
+                let mut chart = builder.build_cartesian_2d(x_range.log_scale(), y_range).expect("Failed to build chart");
+                draw_plane(&mut chart, &cells, &contours, &x_desc, &y_desc);
+            },
+            (true, true) => {
+                let mut chart = builder.build_cartesian_2d(x_range.log_scale(), y_range.log_scale()).expect("Failed to build chart");
+                draw_plane(&mut chart, &cells, &contours, &x_desc, &y_desc);
+            },
+        }
+
+        draw_colorbar(&colorbar_area, &scale, &plane.metadata.variable);
+    }
+
+    // Return "file" and actual range
+    (svg_string, plotted_ranges)
+}
@@ -0,0 +1,327 @@
+use crate::data::{series_name_of_line, DataLine, DataSlice, DatafileReadError, FileFormat, LineMetadata, LineProblems, LoadReport, LoadSettings, ReadProblem, SeriesSlice, StepSelector, Time};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Number of decoded slices kept around, e.g. for stepping back and forth
+const CACHE_CAPACITY: usize = 256;
+
+/// The start of a block of lines at one time, found while indexing a file without parsing its points
+#[derive(Debug, Clone)]
+pub struct BlockIndex {
+    pub time: Time,
+    pub start: u64, // byte offset
+    pub metadata: Option<LineMetadata>, // for naming the series, and for lines that have no header of their own
+}
+
+/// Reads the lines of a file together with the byte offsets they start at
+pub fn lines_with_offsets<R: BufRead>(mut reader: R) -> impl Iterator<Item = Result<(u64, String)>> {
+    let mut offset = 0;
+    std::iter::from_fn(move || {
+        let mut line = String::new();
+        match reader.read_line(&mut line) {
+            Ok(0) => None,
+            Ok(length) => {
+                let start = offset;
+                offset += length as u64;
+                Some(Ok((start, line.trim_end_matches(&['\n', '\r'][..]).to_string())))
+            },
+            Err(e) => Some(Err(e.into())),
+        }
+    })
+}
+
+/// Only uncompressed files in formats made of blocks can be indexed, and only when a single y column
+/// is read, since each block is indexed as part of one series
+pub fn can_index(filename: &str, settings: &LoadSettings) -> bool {
+    use crate::compression::{open_file, Compression};
+
+    if settings.columns.has_several_y() {
+        return false;
+    }
+    match open_file(filename) {
+        Ok((Compression::None, mut reader)) => match reader.fill_buf() {
+            Ok(start) => !matches!(FileFormat::detect(filename, start), FileFormat::Delimited(_)),
+            Err(_) => false,
+        },
+        _ => false,
+    }
+}
+
+/// A file whose blocks are read when they are needed
+#[derive(Clone)]
+struct IndexedFile {
+    filename: String,
+    format: FileFormat,
+    header: Vec<u8>, // leading comments, describing the columns of Carpet files
+    blocks: Vec<(u64, u64, Option<LineMetadata>)>, // start and end offsets, and metadata of each block
+}
+
+impl IndexedFile {
+    /// Indexes the blocks of a file. Only problems found while indexing are reported, e.g. bad time
+    /// values, since points are not looked at until they are needed.
+    fn new(filename: &str, settings: &LoadSettings, problems: &mut LineProblems) -> Result<(IndexedFile, Vec<BlockIndex>)> {
+        use std::fs::File;
+        use std::io::{BufReader, Read};
+
+        let file = File::open(filename)?;
+        let length = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+        let format = FileFormat::detect(filename, reader.fill_buf()?);
+
+        let index = match format {
+            FileFormat::YGraph => crate::data::index_ygraph_blocks(reader, problems)?,
+            FileFormat::CarpetAscii => {
+                use crate::carpet::{axis_from_filename, index_carpet_blocks};
+                index_carpet_blocks(reader, &settings.columns, axis_from_filename(filename), problems)?
+            },
+            FileFormat::Gnuplot => crate::gnuplot::index_gnuplot_blocks(reader, problems)?,
+            FileFormat::Delimited(_) => Vec::new(),
+        };
+
+        let mut header = Vec::new();
+        if let (FileFormat::CarpetAscii, Some(first)) = (format, index.first()) {
+            File::open(filename)?.take(first.start).read_to_end(&mut header)?;
+        }
+
+        let ends = index.iter().skip(1).map(|b| b.start).chain(std::iter::once(length));
+        let blocks = index.iter().zip(ends).map(|(b, end)| (b.start, end, b.metadata.clone())).collect();
+
+        let indexed_file = IndexedFile {
+            filename: filename.to_string(),
+            format,
+            header,
+            blocks,
+        };
+        Ok((indexed_file, index))
+    }
+
+    /// Reads and parses one block, which the time filter has already been applied to
+    fn read_block(&self, block: usize, settings: &LoadSettings) -> Result<Vec<(Time, DataLine)>> {
+        use std::fs::File;
+        use std::io::{BufReader, Read, Seek, SeekFrom};
+
+        let (start, end, metadata) = &self.blocks[block];
+        let mut file = File::open(&self.filename)?;
+        file.seek(SeekFrom::Start(*start))?;
+        let reader = BufReader::new(self.header.as_slice().chain(file.take(end - start)));
+
+        let mut time_line_pairs = crate::data::read_datalines(reader, self.format, &self.filename, settings,
+                                                              &mut StepSelector::all(), &mut LineProblems::new())?;
+        for (_, line) in time_line_pairs.iter_mut() {
+            match (&mut line.metadata, metadata) {
+                (None, _) => line.metadata = metadata.clone(),
+                // The series comment of an exported gnuplot file comes before the block
+                (Some(line_metadata), Some(metadata)) if line_metadata.series.is_none() => {
+                    line_metadata.series = metadata.series.clone();
+                },
+                _ => {},
+            }
+        }
+        Ok(time_line_pairs)
+    }
+}
+
+/// A series whose slices are made of blocks of indexed files
+#[derive(Clone)]
+struct LazySeries {
+    name: String,
+    steps: Vec<(Time, Vec<(usize, usize)>)>, // sorted by time, with the file and block numbers of each step
+}
+
+/// Decoded slices, the most recently used last
+#[derive(Clone)]
+struct SliceCache {
+    entries: VecDeque<((usize, usize), Arc<DataSlice>)>, // series and step numbers, and the slice
+}
+
+impl SliceCache {
+    fn get(&mut self, key: (usize, usize)) -> Option<Arc<DataSlice>> {
+        let position = self.entries.iter().position(|(k, _)| *k == key)?;
+        let entry = self.entries.remove(position)?;
+        let slice = Arc::clone(&entry.1);
+        self.entries.push_back(entry);
+        Some(slice)
+    }
+
+    fn insert(&mut self, key: (usize, usize), slice: Arc<DataSlice>) {
+        if self.entries.len() >= CACHE_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((key, slice));
+    }
+}
+
+/// Data read on demand: files are only indexed when they are loaded, and the slices of a step
+/// are read when they are shown. A limited number of decoded slices is kept.
+#[derive(Clone)]
+pub struct OnDemandData {
+    files: Vec<IndexedFile>,
+    series: Vec<LazySeries>,
+    settings: LoadSettings,
+    cache: RefCell<SliceCache>,
+}
+
+impl OnDemandData {
+    /// Indexes a set of files, adding the problems found and overlapping restarts to the report
+    pub fn from_files(filenames: &[String], settings: &LoadSettings, report: &mut LoadReport) -> OnDemandData {
+        use crate::restart::{directory_of, is_superseded, Segments};
+        use rayon::prelude::*;
+
+        let results: Vec<_> = filenames.par_iter()
+            .map(|filename| {
+                let mut problems = LineProblems::new();
+                let result = IndexedFile::new(filename, settings, &mut problems).map_err(|e| e.to_string());
+                (result, problems)
+            })
+            .collect();
+
+        let mut data = OnDemandData {
+            files: Vec::new(),
+            series: Vec::new(),
+            settings: settings.clone(),
+            cache: RefCell::new(SliceCache { entries: VecDeque::new() }),
+        };
+        let mut blocks = Vec::new(); // series name, directory, time, and file and block number
+        for (filename, (result, problems)) in filenames.iter().zip(results) {
+            let error = |line, problem| DatafileReadError {
+                filename: filename.clone(),
+                line,
+                problem,
+            };
+            let (file, index) = match result {
+                Ok(indexed) => indexed,
+                Err(reason) => {
+                    report.errors.push(error(None, ReadProblem::Unreadable(reason)));
+                    continue;
+                },
+            };
+            report.errors.extend(problems.into_iter().map(|(line, problem)| error(Some(line), problem)));
+            if index.is_empty() {
+                report.errors.push(error(None, ReadProblem::NoData));
+            }
+            let file_number = data.files.len();
+            // Blocks outside the time filter are never read
+            let mut selector = StepSelector::new(settings.time_filter, settings.time_tolerance);
+            for (block_number, block) in index.into_iter().enumerate() {
+                if !selector.keeps(block.time) {
+                    continue;
+                }
+                let name = series_name_of_line(&DataLine::new(block.metadata, &[]), filename);
+                blocks.push((name, directory_of(filename), block.time, (file_number, block_number)));
+            }
+            data.files.push(file);
+        }
+
+        // Like for files read completely, only the newest segment of a restarted run is kept
+        let mut overlaps = Vec::new();
+        if settings.merge_restarts {
+            let mut segments = Segments::new();
+            for (name, directory, time, _) in &blocks {
+                segments.add(name, directory, *time);
+            }
+            overlaps = segments.overlaps(&settings.time_tolerance);
+        }
+        for (name, directory, time, block) in blocks {
+            if !is_superseded(&mut overlaps, &name, &directory, time, &settings.time_tolerance) {
+                data.insert_block(name, time, block);
+            }
+        }
+        report.restarts.extend(overlaps);
+
+        data
+    }
+
+    fn insert_block(&mut self, name: String, time: Time, block: (usize, usize)) {
+        let series = match self.series.iter_mut().position(|s| s.name == name) {
+            Some(index) => &mut self.series[index],
+            None => {
+                self.series.push(LazySeries {
+                    name,
+                    steps: Vec::new(),
+                });
+                self.series.last_mut().unwrap()
+            },
+        };
+        // Blocks of matching times make up one step
+        match self.settings.time_tolerance.search(&series.steps, time, |(t, _)| *t) {
+            Ok(index) => series.steps[index].1.push(block),
+            Err(index) => series.steps.insert(index, (time, vec![block])),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.series.is_empty()
+    }
+
+    /// Adds the series of more indexed files
+    pub fn merge(&mut self, other: OnDemandData) {
+        let file_offset = self.files.len();
+        self.files.extend(other.files);
+        for series in other.series {
+            for (time, blocks) in series.steps {
+                for (file_number, block_number) in blocks {
+                    self.insert_block(series.name.clone(), time, (file_number + file_offset, block_number));
+                }
+            }
+        }
+        // Steps may have moved, so cached slices no longer belong to their numbers
+        self.cache.borrow_mut().entries.clear();
+    }
+
+    pub fn times(&self) -> Vec<Time> {
+        self.series.iter().flat_map(|s| s.steps.iter().map(|(t, _)| *t)).collect()
+    }
+
+    /// Returns the slice of each series that is current at the given time, reading it if needed
+    pub fn at_time(&self, time: Time) -> Vec<SeriesSlice> {
+        let mut series_slices = Vec::new();
+        for (series_number, series) in self.series.iter().enumerate() {
+            // The latest step not after the given time, or close enough to it
+            let latest = self.settings.time_tolerance.latest_match(time);
+            let step = series.steps.partition_point(|(t, _)| *t <= latest);
+            if step == 0 {
+                continue;
+            }
+            let key = (series_number, step - 1);
+            let cached = self.cache.borrow_mut().get(key);
+            let slice = match cached {
+                Some(slice) => slice,
+                None => {
+                    let slice = Arc::new(self.read_step(series, step - 1));
+                    self.cache.borrow_mut().insert(key, Arc::clone(&slice));
+                    slice
+                },
+            };
+            series_slices.push(SeriesSlice {
+                name: series.name.clone(),
+                slice,
+            });
+        }
+        series_slices
+    }
+
+    /// Reads the blocks making up one step of a series. Blocks that can no longer be read, e.g.
+    /// because their file was changed, are left out.
+    fn read_step(&self, series: &LazySeries, step: usize) -> DataSlice {
+        let (time, blocks) = &series.steps[step];
+        let mut slice = DataSlice {
+            time: *time,
+            datalines: Vec::new(),
+        };
+        for (file_number, block_number) in blocks {
+            let file = &self.files[*file_number];
+            match file.read_block(*block_number, &self.settings) {
+                Ok(time_line_pairs) => slice.datalines.extend(time_line_pairs.into_iter()
+                    .map(|(_, l)| l)
+                    .filter(|l| series_name_of_line(l, &file.filename) == series.name && !l.points.is_empty())),
+                Err(e) => eprintln!("Could not read {}: {}", file.filename, e),
+            }
+        }
+        slice.sort();
+        slice
+    }
+}
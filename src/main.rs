@@ -2,6 +2,21 @@ mod plotting;
 mod ui;
 mod data;
 mod state;
+mod carpet;
+mod delimited;
+mod gnuplot;
+mod compression;
+mod cache;
+mod lazy;
+mod restart;
+mod simfactory;
+mod stream;
+mod socket;
+mod composite;
+mod contour;
+mod plane;
+mod scalars;
+mod heatmap;
 
 use glib::clone;
 use gtk::prelude::*;
@@ -25,21 +40,43 @@ fn main() {
     let state = State::new();
 
     let state_cell = Rc::new(RefCell::new(state));
+
+    application.add_main_option("listen", glib::Char::from(b'l'), glib::OptionFlags::NONE, glib::OptionArg::String,
+                                "Listen for data sent by a running simulation to a Unix socket at PATH, or to a TCP port on localhost",
+                                Some("PATH|PORT"));
+    application.connect_handle_local_options(clone!(@weak state_cell => @default-return -1, move |_, options| {
+        if let Ok(Some(address)) = options.lookup::<String>("listen") {
+            if let Err(e) = state_cell.borrow_mut().listen(&address) {
+                eprintln!("{}", e);
+                return 1;
+            }
+        }
+        // Go on as usual
+        -1
+    }));
     application.connect_activate(clone!(@weak state_cell => move |app| {
         build_ui(app, state_cell);
     }));
 
-    use data::Data;
     application.connect_open(clone!(@weak state_cell => move |app, files, _hint| {
         let mut filenames = Vec::new();
         for file in files {
-            let filename = file.path()
-                .expect("Error accessing file")
-                .as_path().display().to_string();
-            filenames.push(filename);
+            let path = file.path()
+                .expect("Error accessing file");
+            // gio takes "-" for a file in the current directory
+            if path.file_name() == Some(std::ffi::OsStr::new(stream::STDIN)) && !path.exists() {
+                filenames.push(stream::STDIN.to_string());
+                continue;
+            }
+            // Variables of a simulation are picked once the window is shown
+            if simfactory::is_simulation(&path) {
+                state_cell.borrow_mut().pending_simulation = Some(path);
+                continue;
+            }
+            filenames.push(path.as_path().display().to_string());
         }
-        if let Some(data) = Data::from_files(filenames) {
-            state_cell.borrow_mut().load_data(data);
+        if !filenames.is_empty() {
+            state_cell.borrow_mut().load_files(filenames);
         }
 
         app.activate();
@@ -0,0 +1,193 @@
+use crate::data::{LineProblems, LoadSettings, StepSelector, Time, TimeTolerance};
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Everything read from a 2D file: its metadata, its patches with their times, and its problems
+pub type PlaneContents = (PlaneMetadata, Vec<(Time, Patch)>, LineProblems);
+
+/// Values on a rectangular grid, e.g. one component of one refinement level of 2D output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Patch {
+    pub xs: Vec<f64>, // ascending
+    pub ys: Vec<f64>, // ascending
+    pub values: Vec<f64>, // one row for each y, NaN where the grid has no point
+    pub refinement_level: Option<u32>,
+}
+
+impl Patch {
+    /// Arranges points (x, y, value) on the grid of their coordinates. Points whose coordinates are
+    /// not finite have no place on the grid and are left out.
+    pub fn from_points(points: &[(f64, f64, f64)], refinement_level: Option<u32>) -> Patch {
+        let points: Vec<&(f64, f64, f64)> = points.iter().filter(|(x, y, _)| x.is_finite() && y.is_finite()).collect();
+        let coordinates = |pick: fn(&&(f64, f64, f64)) -> f64| {
+            let mut coordinates: Vec<f64> = points.iter().map(pick).collect();
+            coordinates.sort_by(|a, b| a.total_cmp(b));
+            coordinates.dedup_by(|a, b| same_coordinate(*a, *b));
+            coordinates
+        };
+        let xs = coordinates(|p| p.0);
+        let ys = coordinates(|p| p.1);
+
+        let mut values = vec![f64::NAN; xs.len() * ys.len()];
+        for (x, y, value) in points {
+            values[nearest(&ys, *y) * xs.len() + nearest(&xs, *x)] = *value;
+        }
+        Patch {
+            xs,
+            ys,
+            values,
+            refinement_level,
+        }
+    }
+
+    /// The value at the i-th x and the j-th y
+    pub fn value(&self, i: usize, j: usize) -> f64 {
+        self.values[j * self.xs.len() + i]
+    }
+}
+
+/// Index of the coordinate closest to c, in a sorted list
+fn nearest(coordinates: &[f64], c: f64) -> usize {
+    let i = coordinates.partition_point(|v| *v < c);
+    if i > 0 && (i == coordinates.len() || c - coordinates[i-1] < coordinates[i] - c) {
+        i - 1
+    } else {
+        i
+    }
+}
+
+/// Coordinates of the same grid point, printed with different rounding
+fn same_coordinate(c1: f64, c2: f64) -> bool {
+    (c1 - c2).abs() <= 1e-12 * c1.abs().max(c2.abs()).max(1.0)
+}
+
+/// The patches of a plane at one time
+#[derive(Debug, Clone)]
+pub struct PlaneSlice {
+    pub time: Time,
+    pub patches: Vec<Patch>,
+}
+
+/// Names of the axes and of the variable of a plane
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlaneMetadata {
+    pub axes: (String, String),
+    pub variable: String,
+}
+
+impl PlaneMetadata {
+    /// Name of the series a plane with this metadata belongs to, e.g. "rho (xy)"
+    pub fn series_name(&self) -> String {
+        format!("{} ({}{})", self.variable, self.axes.0.to_lowercase(), self.axes.1.to_lowercase())
+    }
+}
+
+/// A named sequence of plane slices, e.g. the 2D output of one variable
+#[derive(Clone)]
+pub struct PlaneSeries {
+    pub name: String,
+    pub metadata: PlaneMetadata,
+    pub slices: Vec<Arc<PlaneSlice>>, // sorted by time
+}
+
+impl PlaneSeries {
+    /// Collects (time, patch) pairs into slices of matching time, like `Series` does with lines
+    pub fn from_time_patch_pairs(metadata: PlaneMetadata, mut time_patch_pairs: Vec<(Time, Patch)>,
+                                 tolerance: &TimeTolerance) -> PlaneSeries {
+        time_patch_pairs.sort_by(|(t1, _), (t2, _)| t1.total_cmp(t2));
+
+        let mut slices: Vec<PlaneSlice> = Vec::new();
+        for (time, patch) in time_patch_pairs {
+            match slices.last_mut() {
+                Some(slice) if tolerance.matches(slice.time, time) => slice.patches.push(patch),
+                _ => slices.push(PlaneSlice {
+                    time,
+                    patches: vec![patch],
+                }),
+            }
+        }
+
+        PlaneSeries {
+            name: metadata.series_name(),
+            metadata,
+            slices: slices.into_iter().map(Arc::new).collect(),
+        }
+    }
+
+    /// Returns the latest slice at or before the given time (or close enough to it), if there is one
+    pub fn at_time(&self, time: Time, tolerance: &TimeTolerance) -> Option<Arc<PlaneSlice>> {
+        let latest = tolerance.latest_match(time);
+        let index = self.slices.partition_point(|s| s.time <= latest);
+        index.checked_sub(1).map(|i| Arc::clone(&self.slices[i]))
+    }
+
+    pub fn times(&self) -> Vec<Time> {
+        self.slices.iter().map(|s| s.time).collect()
+    }
+
+    /// Adds the slices of another series, combining slices of matching time
+    pub fn merge(&mut self, other: PlaneSeries, tolerance: &TimeTolerance) {
+        for slice in other.slices {
+            match tolerance.search(&self.slices, slice.time, |s| s.time) {
+                Ok(index) => Arc::make_mut(&mut self.slices[index]).patches.extend(slice.patches.iter().cloned()),
+                Err(index) => self.slices.insert(index, slice),
+            }
+        }
+    }
+}
+
+/// The slice of a single plane series at some time, labeled with the name of that series
+#[derive(Clone)]
+pub struct PlaneSeriesSlice {
+    pub name: String,
+    pub metadata: PlaneMetadata,
+    pub slice: Arc<PlaneSlice>,
+}
+
+/// Whether a file holds planes rather than lines, e.g. "rho.xy.asc"
+pub fn is_plane_file(filename: &str) -> bool {
+    crate::carpet::plane_from_filename(filename).is_some()
+}
+
+/// Reads the patches of a 2D file, decompressing it first if needed, along with any problems found
+/// in it
+pub fn read_plane_file(filename: &str, settings: &LoadSettings) -> Result<PlaneContents> {
+    use crate::carpet::{plane_from_filename, read_carpet_patches};
+    use crate::compression::open_file;
+
+    let plane = plane_from_filename(filename).ok_or("not a file of 2D output")?;
+    let (_, reader) = open_file(filename)?;
+    let mut problems = LineProblems::new();
+    let mut selector = StepSelector::new(settings.time_filter, settings.time_tolerance);
+    let (metadata, time_patch_pairs) = read_carpet_patches(reader, &settings.columns, plane, &mut selector, &mut problems)?;
+    Ok((metadata, time_patch_pairs, problems))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn points_off_the_grid_are_left_out() {
+        let points = [(0.0, 0.0, 1.0), (1.0, 0.0, 2.0), (f64::NAN, 0.0, 3.0), (0.0, 1.0, 4.0), (1.0, f64::INFINITY, 5.0)];
+        let patch = Patch::from_points(&points, None);
+        assert_eq!(patch.xs, vec![0.0, 1.0]);
+        assert_eq!(patch.ys, vec![0.0, 1.0]);
+        assert_eq!(patch.values[..3], [1.0, 2.0, 4.0]);
+        assert!(patch.value(1, 1).is_nan());
+    }
+
+    #[test]
+    fn patches_without_a_number_for_time_do_not_panic() {
+        let metadata = PlaneMetadata {
+            axes: ("x".to_string(), "y".to_string()),
+            variable: "rho".to_string(),
+        };
+        let patch = Patch::from_points(&[(0.0, 0.0, 1.0)], None);
+        let pairs = vec![(1.0, patch.clone()), (f64::NAN, patch.clone()), (0.0, patch)];
+        let series = PlaneSeries::from_time_patch_pairs(metadata, pairs, &TimeTolerance::new());
+        assert_eq!(series.slices[0].time, 0.0);
+        assert_eq!(series.slices[1].time, 1.0);
+    }
+}
@@ -1,10 +1,19 @@
-use crate::data::{DataSlice, Point};
+use crate::composite::composite;
+use crate::contour::ContourLevels;
+use crate::data::{ChannelKind, DataLine, LineMetadata, Point, SeriesSlice, Time};
 
 use plotters::prelude::*;
+use plotters::coord::ranged1d::ValueFormatter;
 use rayon::prelude::*;
+use std::borrow::Cow;
+use std::sync::Arc;
 
 pub type Range = (f64, f64);
 
+/// Space reserved below and left of the plot for tick labels and axis titles, in pixels
+pub const X_LABEL_AREA_SIZE: u32 = 35;
+pub const Y_LABEL_AREA_SIZE: u32 = 65;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PlotRange {
     Auto,
@@ -20,6 +29,44 @@ impl PlotRange {
     }
 }
 
+/// Which part of complex values to plot, for lines that carry an imaginary part
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ComplexPart {
+    Magnitude,
+    Phase,
+    Real,
+    Imaginary,
+}
+
+impl ComplexPart {
+    fn of(&self, re: f64, im: f64) -> f64 {
+        match self {
+            ComplexPart::Magnitude => re.hypot(im),
+            ComplexPart::Phase => im.atan2(re),
+            ComplexPart::Real => re,
+            ComplexPart::Imaginary => im,
+        }
+    }
+
+    /// Axis title for this part of a variable, e.g. "|psi4|"
+    fn describe(&self, variable: &str) -> String {
+        match self {
+            ComplexPart::Magnitude => format!("|{}|", variable),
+            ComplexPart::Phase => format!("arg {}", variable),
+            ComplexPart::Real => format!("Re {}", variable),
+            ComplexPart::Imaginary => format!("Im {}", variable),
+        }
+    }
+}
+
+/// How uncertainties of y are drawn, for lines that carry them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UncertaintyStyle {
+    Hidden,
+    Bars,
+    Band,
+}
+
 #[derive(Debug, Clone)]
 pub struct PlotSettings {
     pub plot_range_x: PlotRange,
@@ -29,6 +76,12 @@ pub struct PlotSettings {
     pub use_color: bool,
     pub use_logscale_x: bool,
     pub use_logscale_y: bool,
+    pub use_logscale_color: bool, // of heatmaps
+    pub draw_heatmap: bool, // colors of 2D data, which may be left out to show only contours
+    pub contour_levels: Option<ContourLevels>, // contour lines over 2D data
+    pub composite_lines: bool, // merge the lines of each step into one, see composite::composite
+    pub complex_part: ComplexPart,
+    pub uncertainty_style: UncertaintyStyle,
 }
 
 impl PlotSettings {
@@ -41,6 +94,12 @@ impl PlotSettings {
             use_color: true,
             use_logscale_x: false,
             use_logscale_y: false,
+            use_logscale_color: false,
+            draw_heatmap: true,
+            contour_levels: None,
+            composite_lines: false,
+            complex_part: ComplexPart::Magnitude,
+            uncertainty_style: UncertaintyStyle::Bars,
         }
     }
 }
@@ -99,7 +158,7 @@ fn truncate_line_segment(line_segment: &(Point, Point), x_range: &Range, y_range
 }
 
 /// Takes a line of data points and returns a vector of lines that lie within the specified area
-fn truncate_line(line: &[Point], x_range: &Range, y_range: &Range) -> Vec<Vec<Point>> {
+pub(crate) fn truncate_line(line: &[Point], x_range: &Range, y_range: &Range) -> Vec<Vec<Point>> {
     if line.is_empty() {
         return Vec::new();
     }
@@ -167,18 +226,206 @@ fn truncate_line(line: &[Point], x_range: &Range, y_range: &Range) -> Vec<Vec<Po
     selected_pieces
 }
 
-/// Take a vector of vectors of points, and plot them into an SVG file, returned as a String
-pub fn plot_data_slice_to_svg(data_slice: &DataSlice, plot_settings: &PlotSettings, image_size: &(u32, u32)) -> (String, (PlotRange, PlotRange))
+/// The points of a line as plotted, and the uncertainty of each of them. Complex lines are shown as
+/// the chosen part, and keep their uncertainties only if that is the real part.
+fn displayed_line<'a>(line: &'a DataLine, plot_settings: &PlotSettings) -> (Cow<'a, [Point]>, Option<&'a [f64]>) {
+    let uncertainty = line.channel(ChannelKind::Uncertainty);
+    match line.channel(ChannelKind::Imaginary) {
+        Some(imaginary) => {
+            let part = plot_settings.complex_part;
+            let points = line.points.iter().zip(imaginary).map(|((x, re), im)| (*x, part.of(*re, *im))).collect();
+            (Cow::Owned(points), uncertainty.filter(|_| part == ComplexPart::Real))
+        },
+        None => (Cow::Borrowed(&line.points), uncertainty),
+    }
+}
+
+/// Takes the points of a line and their uncertainties, and returns (x, y - σ, y, y + σ) for the
+/// points within the x range, with all values moved into the y range
+fn uncertainty_intervals(line: &[Point], uncertainty: &[f64], x_range: &Range, y_range: &Range) -> Vec<(f64, f64, f64, f64)> {
+    let (xmin, xmax) = *x_range;
+    let (ymin, ymax) = *y_range;
+    let clamp = |y: f64| y.max(ymin).min(ymax);
+    line.iter().zip(uncertainty)
+        .filter(|((x, _), sigma)| *x >= xmin && *x <= xmax && sigma.is_finite())
+        .map(|((x, y), sigma)| (*x, clamp(y - sigma.abs()), clamp(*y), clamp(y + sigma.abs())))
+        .collect()
+}
+
+/// Everything that goes onto a chart, already filtered to the plotted area
+struct ChartContents {
+    line_data: Vec<Vec<Vec<Point>>>,
+    point_data: Vec<Vec<Point>>,
+    uncertainty_data: Vec<Vec<(f64, f64, f64, f64)>>,
+    color_indices: Vec<usize>,
+    labels: Vec<(String, usize)>,
+    x_desc: Option<String>,
+    y_desc: Option<String>,
+    cursor: Vec<Point>, // vertical line at the current time, in time series plots
+}
+
+/// Draws line and point data onto a chart, and adds a legend if labels are given
+fn draw_data<'a, DB, X, Y>(chart: &mut ChartContext<'a, DB, Cartesian2d<X, Y>>,
+                           contents: &ChartContents,
+                           colors: &[RGBColor],
+                           plot_settings: &PlotSettings)
+where DB: DrawingBackend + 'a,
+      X: Ranged<ValueType = f64> + ValueFormatter<f64>,
+      Y: Ranged<ValueType = f64> + ValueFormatter<f64>,
 {
-    let data = &data_slice.datalines;
+    let point_size = 2; // Point Size
+    let n_colors = colors.len();
+    let ChartContents { line_data, point_data, uncertainty_data, color_indices, labels, .. } = contents;
+
+    let mut mesh = chart.configure_mesh();
+    if let Some(x_desc) = &contents.x_desc {
+        mesh.x_desc(x_desc);
+    }
+    if let Some(y_desc) = &contents.y_desc {
+        mesh.y_desc(y_desc);
+    }
+    mesh.draw().expect("Failed to draw mesh");
+
+    // uncertainties, below the lines and points:
+    for (intervals, color_index) in uncertainty_data.iter().zip(color_indices) {
+        let color = &colors[color_index%n_colors];
+        match plot_settings.uncertainty_style {
+            UncertaintyStyle::Hidden => {},
+            UncertaintyStyle::Bars => {
+                chart.draw_series(intervals.iter().map(|(x, lower, y, upper)| {
+                    ErrorBar::new_vertical(*x, *lower, *y, *upper, color, point_size * 2)
+                })).expect("Failed to draw error bars");
+            },
+            UncertaintyStyle::Band => {
+                let outline: Vec<Point> = intervals.iter().map(|(x, _, _, upper)| (*x, *upper))
+                    .chain(intervals.iter().rev().map(|(x, lower, _, _)| (*x, *lower)))
+                    .collect();
+                chart.draw_series(std::iter::once(Polygon::new(outline, color.mix(0.2))))
+                    .expect("Failed to draw error band");
+            },
+        }
+    }
+
+    // plot each point vector seperately
+    // line:
+    if plot_settings.draw_lines {
+        for (line_segments, color_index) in line_data.iter().zip(color_indices) {
+            let color = &colors[color_index%n_colors];
+            for segment in line_segments {
+                chart.draw_series(LineSeries::new(segment.clone(), color))
+                    .expect("Failed to draw line");
+            }
+        }
+    }
+    // points:
+    if plot_settings.draw_points {
+        for (points, color_index) in point_data.iter().zip(color_indices) {
+            let color = &colors[color_index%n_colors];
+            chart.draw_series(PointSeries::of_element(
+                points.clone(),
+                point_size,
+                color,
+                &|coord, size, style| {
+                    EmptyElement::at(coord)
+                        + Circle::new((0,0), size, style.filled())
+                }
+                )).expect("Failed to draw points");
+        }
+    }
+
+    // current time:
+    if !contents.cursor.is_empty() {
+        chart.draw_series(LineSeries::new(contents.cursor.clone(), BLACK.mix(0.5)))
+            .expect("Failed to draw time cursor");
+    }
+
+    // legend:
+    if !labels.is_empty() {
+        for (label, color_index) in labels {
+            // Empty series that only serve to carry the label
+            let color = colors[color_index%n_colors];
+            chart.draw_series(LineSeries::new(Vec::<Point>::new(), &color))
+                .expect("Failed to draw legend entry")
+                .label(label)
+                .legend(move |(x, y)| PathElement::new(vec![(x, y), (x + 20, y)], color));
+        }
+        chart.configure_series_labels()
+            .background_style(WHITE.mix(0.8))
+            .border_style(BLACK)
+            .draw()
+            .expect("Failed to draw legend");
+    }
+}
+
+/// Take the slices of all series at one time, and plot them into an SVG file, returned as a String
+pub fn plot_data_slice_to_svg(series_slices: &[SeriesSlice], plot_settings: &PlotSettings, image_size: &(u32, u32)) -> (String, (PlotRange, PlotRange))
+{
+    let composite_slices: Vec<SeriesSlice>;
+    let series_slices = if plot_settings.composite_lines {
+        composite_slices = series_slices.iter().map(|s| SeriesSlice {
+            name: s.name.clone(),
+            slice: Arc::new(composite(&s.slice)),
+        }).collect();
+        &composite_slices[..]
+    } else {
+        series_slices
+    };
+    plot_lines_to_svg(series_slices, None, plot_settings, image_size)
+}
+
+/// Plot time series against time into an SVG file, returned as a String, with a vertical line at
+/// the current time
+pub fn plot_time_series_to_svg(time_series: &[SeriesSlice], time: Time, plot_settings: &PlotSettings, image_size: &(u32, u32)) -> (String, (PlotRange, PlotRange))
+{
+    plot_lines_to_svg(time_series, Some(time), plot_settings, image_size)
+}
+
+/// Plots lines into an SVG file, returned as a String, optionally marking a time along x
+fn plot_lines_to_svg(series_slices: &[SeriesSlice], cursor: Option<Time>, plot_settings: &PlotSettings, image_size: &(u32, u32)) -> (String, (PlotRange, PlotRange))
+{
+
+    // With several series, lines are colored by the series they belong to and labeled in a legend.
+    // A single series keeps a different color for each of its lines.
+    let color_by_series = series_slices.len() > 1;
+    let mut data: Vec<Cow<[Point]>> = Vec::new();
+    let mut uncertainties: Vec<Option<&[f64]>> = Vec::new();
+    let mut color_indices: Vec<usize> = Vec::new();
+    let mut labels: Vec<(String, usize)> = Vec::new();
+    for (i, series_slice) in series_slices.iter().enumerate() {
+        for (j, line) in series_slice.slice.datalines.iter().enumerate() {
+            let (points, uncertainty) = displayed_line(line, plot_settings);
+            data.push(points);
+            uncertainties.push(uncertainty);
+            color_indices.push(if color_by_series { i } else { j });
+        }
+        if color_by_series {
+            labels.push((series_slice.name.clone(), i));
+        }
+    }
+
+    // Axis titles come from the file headers: the axis along x, and the variable along y if there is only one
+    let metadata: Vec<&LineMetadata> = series_slices.iter()
+        .flat_map(|s| s.slice.datalines.iter())
+        .filter_map(|l| l.metadata.as_ref())
+        .collect();
+    let x_desc = metadata.first().map(|m| m.axis.to_lowercase());
+    let y_desc = match metadata.first() {
+        Some(first) if metadata.iter().all(|m| m.variable == first.variable) => Some(first.variable.clone()),
+        _ => None,
+    };
+    let is_complex = series_slices.iter()
+        .flat_map(|s| s.slice.datalines.iter())
+        .any(|l| l.channel(ChannelKind::Imaginary).is_some());
+    let y_desc = match y_desc {
+        Some(variable) if is_complex => Some(plot_settings.complex_part.describe(&variable)),
+        y_desc => y_desc,
+    };
 
     // Parameters
-    let point_size = 2; // Point Size
     let colors = match plot_settings.use_color {
         true => vec![BLACK, BLUE, CYAN, GREEN, MAGENTA, RED, YELLOW],
         false => vec![BLACK],
     };
-    let n_colors = colors.len();
     let x_padding = 0.0;
     let y_padding = 0.02;
 
@@ -188,7 +435,7 @@ pub fn plot_data_slice_to_svg(data_slice: &DataSlice, plot_settings: &PlotSettin
         PlotRange::Auto => {
             let mut xmaxs = Vec::new();
             let mut xmins = Vec::new();
-            for series in data {
+            for series in &data {
                 if !series.is_empty() {
                     xmaxs.push(series.par_iter().max_by(|(x1,_),(x2,_)| x1.partial_cmp(x2).unwrap()).unwrap().0);
                     xmins.push(series.par_iter().min_by(|(x1,_),(x2,_)| x1.partial_cmp(x2).unwrap()).unwrap().0);
@@ -231,9 +478,23 @@ pub fn plot_data_slice_to_svg(data_slice: &DataSlice, plot_settings: &PlotSettin
         PlotRange::Auto => {
             // Filter the points to only consider those in the specified x-range
             let mut points: Vec<Point> = Vec::new();
-            for series in data {
+            for series in &data {
                 points.extend(series.iter().filter(|(x, _y)| x >= &xmin && x <= &xmax ));
             }
+            // Shown uncertainties are included as points at either end of their interval
+            if plot_settings.uncertainty_style != UncertaintyStyle::Hidden {
+                for (series, uncertainty) in data.iter().zip(&uncertainties) {
+                    for ((x, y), sigma) in series.iter().zip(uncertainty.unwrap_or(&[])) {
+                        if x >= &xmin && x <= &xmax && sigma.is_finite() {
+                            points.push((*x, y + sigma.abs()));
+                            // A lower end at or below zero would squash a logarithmic axis
+                            if !plot_settings.use_logscale_y || y - sigma.abs() > 0.0 {
+                                points.push((*x, y - sigma.abs()));
+                            }
+                        }
+                    }
+                }
+            }
 
             let mut ymin = if !points.is_empty() {
                 points.par_iter().min_by(|(_,y1),(_,y2)| y1.partial_cmp(y2).unwrap()).unwrap().1
@@ -294,6 +555,29 @@ pub fn plot_data_slice_to_svg(data_slice: &DataSlice, plot_settings: &PlotSettin
         truncate_line(&line, &(xmin, xmax), &(ymin, ymax))
     }).collect();
 
+    let uncertainty_data: Vec<Vec<(f64, f64, f64, f64)>> = data.iter().zip(&uncertainties).map(|(line, uncertainty)| {
+        match uncertainty {
+            Some(uncertainty) => uncertainty_intervals(line, uncertainty, &(xmin, xmax), &(ymin, ymax)),
+            None => Vec::new(),
+        }
+    }).collect();
+
+    let cursor = match cursor {
+        Some(t) if t >= xmin && t <= xmax => vec![(t, ymin), (t, ymax)],
+        _ => Vec::new(),
+    };
+
+    let contents = ChartContents {
+        line_data,
+        point_data,
+        uncertainty_data,
+        color_indices,
+        labels,
+        x_desc,
+        y_desc,
+        cursor,
+    };
+
     let x_range = xmin..xmax;
     let y_range = ymin..ymax;
     let logscale_settings = (plot_settings.use_logscale_x, plot_settings.use_logscale_y);
@@ -303,155 +587,42 @@ pub fn plot_data_slice_to_svg(data_slice: &DataSlice, plot_settings: &PlotSettin
         let root = SVGBackend::with_string(&mut svg_string, *image_size).into_drawing_area();
         root.fill(&WHITE).expect("Failed to fill canvas");
 
-        /* Ugly code duplication, but I can't figure out how to work around not being able to make
-           trait objects out of plotters::coord::AsRangedCoord... */
+        // The coordinate types differ between linear and logarithmic axes, so each combination
+        // builds its own chart and then hands it to the same drawing routine
         match logscale_settings {
             (false, false) => {
                 let mut chart = ChartBuilder::on(&root)
-                                .x_label_area_size(20)
-                                .y_label_area_size(50)
+                                .x_label_area_size(X_LABEL_AREA_SIZE)
+                                .y_label_area_size(Y_LABEL_AREA_SIZE)
                                 .build_cartesian_2d(x_range, y_range)
                                 .expect("Failed to build chart");
-
-                chart.configure_mesh().draw().expect("Failed to draw mesh");
-
-                // plot each point vector seperately
-                // line:
-                if plot_settings.draw_lines {
-                    for (i, line_segments) in line_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        for segment in line_segments {
-                            chart.draw_series(LineSeries::new(segment.clone(), color))
-                                .expect("Failed to draw line");
-                        }
-                    }
-                }
-                // points:
-                if plot_settings.draw_points {  
-                    for (i, points) in point_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        chart.draw_series(PointSeries::of_element(
-                            points.clone(),
-                            point_size,
-                            color,
-                            &|coord, size, style| {
-                                EmptyElement::at(coord)
-                                    + Circle::new((0,0), size, style.filled())
-                            }
-                            )).expect("Failed to draw points");
-                    }
-                }
+                draw_data(&mut chart, &contents, &colors, plot_settings);
             },
             (false, true) => {
                 let mut chart = ChartBuilder::on(&root)
-                                .x_label_area_size(20)
-                                .y_label_area_size(50)
+                                .x_label_area_size(X_LABEL_AREA_SIZE)
+                                .y_label_area_size(Y_LABEL_AREA_SIZE)
                                 .build_cartesian_2d(x_range, y_range.log_scale())
                                 .expect("Failed to build chart");
-
-                chart.configure_mesh().draw().expect("Failed to draw mesh");
-
-                // plot each point vector seperately
-                // line:
-                if plot_settings.draw_lines {
-                    for (i, line_segments) in line_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        for segment in line_segments {
-                            chart.draw_series(LineSeries::new(segment.clone(), color))
-                                .expect("Failed to draw line");
-                        }
-                    }
-                }
-                // points:
-                if plot_settings.draw_points {  
-                    for (i, points) in point_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        chart.draw_series(PointSeries::of_element(
-                            points.clone(),
-                            point_size,
-                            color,
-                            &|coord, size, style| {
-                                EmptyElement::at(coord)
-                                    + Circle::new((0,0), size, style.filled())
-                            }
-                            )).expect("Failed to draw points");
-                    }
-                }                
+                draw_data(&mut chart, &contents, &colors, plot_settings);
             },
             (true, false) => {
                 let mut chart = ChartBuilder::on(&root)
-                                .x_label_area_size(20)
-                                .y_label_area_size(50)
+                                .x_label_area_size(X_LABEL_AREA_SIZE)
+                                .y_label_area_size(Y_LABEL_AREA_SIZE)
                                 .build_cartesian_2d(x_range.log_scale(), y_range)
                                 .expect("Failed to build chart");
-
-                chart.configure_mesh().draw().expect("Failed to draw mesh");
-
-                // plot each point vector seperately
-                // line:
-                if plot_settings.draw_lines {
-                    for (i, line_segments) in line_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        for segment in line_segments {
-                            chart.draw_series(LineSeries::new(segment.clone(), color))
-                                .expect("Failed to draw line");
-                        }
-                    }
-                }
-                // points:
-                if plot_settings.draw_points {  
-                    for (i, points) in point_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        chart.draw_series(PointSeries::of_element(
-                            points.clone(),
-                            point_size,
-                            color,
-                            &|coord, size, style| {
-                                EmptyElement::at(coord)
-                                    + Circle::new((0,0), size, style.filled())
-                            }
-                            )).expect("Failed to draw points");
-                    }
-                }
+                draw_data(&mut chart, &contents, &colors, plot_settings);
             },
             (true, true) => {
                 let mut chart = ChartBuilder::on(&root)
-                                .x_label_area_size(20)
-                                .y_label_area_size(50)
+                                .x_label_area_size(X_LABEL_AREA_SIZE)
+                                .y_label_area_size(Y_LABEL_AREA_SIZE)
                                 .build_cartesian_2d(x_range.log_scale(), y_range.log_scale())
                                 .expect("Failed to build chart");
-
-                chart.configure_mesh().draw().expect("Failed to draw mesh");
-
-                // plot each point vector seperately
-                // line:
-                if plot_settings.draw_lines {
-                    for (i, line_segments) in line_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        for segment in line_segments {
-                            chart.draw_series(LineSeries::new(segment.clone(), color))
-                                .expect("Failed to draw line");
-                        }
-                    }
-                }
-                // points:
-                if plot_settings.draw_points {  
-                    for (i, points) in point_data.iter().enumerate() {
-                        let color = &colors[i%n_colors];
-                        chart.draw_series(PointSeries::of_element(
-                            points.clone(),
-                            point_size,
-                            color,
-                            &|coord, size, style| {
-                                EmptyElement::at(coord)
-                                    + Circle::new((0,0), size, style.filled())
-                            }
-                            )).expect("Failed to draw points");
-                    }
-                }
+                draw_data(&mut chart, &contents, &colors, plot_settings);
             },
         }
-
     }
 
     // Return "file" and actual range
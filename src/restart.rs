@@ -0,0 +1,153 @@
+use crate::data::{Time, TimeTolerance};
+use std::fmt;
+
+/// The part of an older segment of a restarted run that was replaced by a newer segment. Segments
+/// are the output directories of a run, e.g. output-0000 and output-0001 of a SimFactory simulation.
+#[derive(Debug, Clone)]
+pub struct RestartOverlap {
+    pub series: String,
+    pub older: String, // directory of the segment that was cut short
+    pub newer: String, // directory of the segment that was kept
+    pub from_time: Time,
+    pub dropped_lines: usize,
+}
+
+impl fmt::Display for RestartOverlap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: kept {} from t = {} on, dropped {} lines of {}",
+               self.series, self.newer, self.from_time, self.dropped_lines, self.older)
+    }
+}
+
+/// The directory a file is in, which is taken as its segment of a run
+pub fn directory_of(filename: &str) -> String {
+    use std::path::Path;
+
+    match Path::new(filename).parent() {
+        Some(parent) => parent.display().to_string(),
+        None => String::new(),
+    }
+}
+
+/// Whether a segment starting at some time restarts a run whose older segment covers the given
+/// time range: it starts strictly later than the older one, but no later than its end. Segments
+/// starting at the same time are separate runs, e.g. two simulations loaded to compare them.
+pub fn is_restart_of(older: (Time, Time), start: Time, tolerance: &TimeTolerance) -> bool {
+    let (older_first, older_last) = older;
+    start > older_first && !tolerance.matches(start, older_first)
+        && (start <= older_last || tolerance.matches(start, older_last))
+}
+
+/// The time range of each segment of each series, collected while loading
+#[derive(Default)]
+pub struct Segments {
+    ranges: Vec<(String, String, Time, Time)>, // series, directory, first and last time
+}
+
+impl Segments {
+    pub fn new() -> Segments {
+        Segments {
+            ranges: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, series: &str, directory: &str, time: Time) {
+        match self.ranges.iter_mut().find(|(s, d, _, _)| s == series && d == directory) {
+            Some((_, _, first, last)) => {
+                *first = first.min(time);
+                *last = last.max(time);
+            },
+            None => self.ranges.push((series.to_string(), directory.to_string(), time, time)),
+        }
+    }
+
+    /// Finds the segments of each series that are overlapped by a segment starting later, see
+    /// `is_restart_of`. Output of several processes in the same directory is one segment, so it is
+    /// never mistaken for a restart.
+    pub fn overlaps(&self, tolerance: &TimeTolerance) -> Vec<RestartOverlap> {
+        let mut ranges = self.ranges.clone();
+        // Segments of the same start are ordered by directory, e.g. output-0000 before output-0001
+        ranges.sort_by(|(s1, d1, first1, _), (s2, d2, first2, _)| {
+            s1.cmp(s2).then(first1.total_cmp(first2)).then(d1.cmp(d2))
+        });
+
+        let mut overlaps = Vec::new();
+        for (i, (series, directory, first, last)) in ranges.iter().enumerate() {
+            // The earliest start of any segment of the same series starting later
+            let newer = ranges[i+1..].iter()
+                .take_while(|(s, _, _, _)| s == series)
+                .find(|(_, _, newer_first, _)| !tolerance.matches(*newer_first, *first));
+            if let Some((_, newer_directory, from_time, _)) = newer {
+                if is_restart_of((*first, *last), *from_time, tolerance) {
+                    overlaps.push(RestartOverlap {
+                        series: series.clone(),
+                        older: directory.clone(),
+                        newer: newer_directory.clone(),
+                        from_time: *from_time,
+                        dropped_lines: 0,
+                    });
+                }
+            }
+        }
+        overlaps
+    }
+}
+
+/// Whether a line of a series at some time, read from a file in the given directory, has been
+/// replaced by a newer segment. Counts the lines found to be replaced.
+pub fn is_superseded(overlaps: &mut [RestartOverlap], series: &str, directory: &str, time: Time, tolerance: &TimeTolerance) -> bool {
+    match overlaps.iter_mut().find(|o| o.series == series && o.older == directory) {
+        Some(overlap) if time >= overlap.from_time || tolerance.matches(time, overlap.from_time) => {
+            overlap.dropped_lines += 1;
+            true
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Segments of rho (x) with the given directories and times
+    fn segments(times: &[(&str, &[Time])]) -> Segments {
+        let mut segments = Segments::new();
+        for (directory, times) in times {
+            for time in times.iter() {
+                segments.add("rho (x)", directory, *time);
+            }
+        }
+        segments
+    }
+
+    #[test]
+    fn later_segment_overlapping_an_older_one_is_a_restart() {
+        let tolerance = TimeTolerance::new();
+        let mut overlaps = segments(&[("output-0000", &[0.0, 1.0, 2.0, 3.0]), ("output-0001", &[2.0, 3.0, 4.0])]).overlaps(&tolerance);
+        assert_eq!(overlaps.len(), 1);
+        assert_eq!(overlaps[0].older, "output-0000");
+        assert_eq!(overlaps[0].newer, "output-0001");
+        assert_eq!(overlaps[0].from_time, 2.0);
+
+        assert!(!is_superseded(&mut overlaps, "rho (x)", "output-0000", 1.0, &tolerance));
+        assert!(is_superseded(&mut overlaps, "rho (x)", "output-0000", 2.0, &tolerance));
+        assert!(!is_superseded(&mut overlaps, "rho (x)", "output-0001", 2.0, &tolerance));
+        assert_eq!(overlaps[0].dropped_lines, 1);
+    }
+
+    #[test]
+    fn segments_of_the_same_start_are_separate_runs() {
+        let tolerance = TimeTolerance::new();
+        let overlaps = segments(&[("sim_a", &[0.0, 1.0, 2.0]), ("sim_b", &[0.0, 1.0, 2.0])]).overlaps(&tolerance);
+        assert!(overlaps.is_empty());
+        assert!(!is_restart_of((0.0, 2.0), 0.0, &tolerance));
+    }
+
+    #[test]
+    fn segment_starting_after_the_end_is_no_restart() {
+        let tolerance = TimeTolerance::new();
+        let overlaps = segments(&[("output-0000", &[0.0, 1.0]), ("output-0001", &[2.0, 3.0])]).overlaps(&tolerance);
+        assert!(overlaps.is_empty());
+        assert!(is_restart_of((0.0, 1.0), 1.0, &tolerance));
+    }
+}
@@ -0,0 +1,276 @@
+use crate::compression::strip_compression_extension;
+use crate::data::{mark_truncated, Column, ColumnSelection, DataLine, DataSlice, LineMetadata, LineProblems, LoadSettings, Point, ReadProblem, SeriesSlice, StepSelector, Time, TimeTolerance};
+use std::io::BufRead;
+use std::sync::Arc;
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Reductions written by CarpetIOScalar, each to a file of its own, e.g. "rho.norm2.asc". A
+/// "scalars" file has all of them, one in each column.
+const REDUCTIONS: [&str; 10] = ["scalars", "minimum", "maximum", "norm1", "norm2", "norm_inf", "infnorm", "average", "sum", "count"];
+
+/// Values of a reduction of a variable over time, e.g. the maximum of rho at each iteration. They
+/// are kept as a single line against time, which plots share rather than copy.
+#[derive(Clone)]
+pub struct TimeSeries {
+    pub name: String, // e.g. "rho (maximum)"
+    pub variable: String,
+    pub reduction: String,
+    slice: Arc<DataSlice>, // of a single line of (time, value) points, sorted by time
+}
+
+impl TimeSeries {
+    pub fn new(variable: &str, reduction: &str) -> TimeSeries {
+        let name = format!("{} ({})", variable, reduction);
+        let metadata = LineMetadata {
+            axis: "time".to_string(),
+            variable: name.clone(),
+            rank: None,
+            grid: None,
+            output_type: None,
+            orientation: None,
+            refinement_level: None,
+            component: None,
+            series: None,
+        };
+        TimeSeries {
+            name,
+            variable: variable.to_string(),
+            reduction: reduction.to_string(),
+            // The time of the slice is not used, the line spans all times
+            slice: Arc::new(DataSlice {
+                time: 0.0,
+                datalines: vec![DataLine::new(Some(metadata), &[])],
+            }),
+        }
+    }
+
+    /// The (time, value) points, sorted by time
+    pub fn points(&self) -> &[Point] {
+        &self.slice.datalines[0].points
+    }
+
+    /// Copies the points only if a plot still uses them
+    fn points_mut(&mut self) -> &mut Vec<Point> {
+        &mut Arc::make_mut(&mut self.slice).datalines[0].points
+    }
+
+    pub fn times(&self) -> Vec<Time> {
+        self.points().iter().map(|(t, _)| *t).collect()
+    }
+
+    /// The whole time series as a single line against time, to be plotted like the lines of a slice
+    pub fn series_slice(&self) -> SeriesSlice {
+        SeriesSlice {
+            name: self.name.clone(),
+            slice: Arc::clone(&self.slice),
+        }
+    }
+
+    /// Adds the points of another time series of the same name. Where they overlap, e.g. in
+    /// segments of a restarted run, the one starting later wins.
+    pub fn merge(&mut self, mut other: TimeSeries, tolerance: &TimeTolerance) {
+        let (start, other_start) = match (self.points().first(), other.points().first()) {
+            (Some((start, _)), Some((other_start, _))) => (*start, *other_start),
+            (None, _) => return *self = other,
+            (_, None) => return,
+        };
+        if other_start >= start {
+            self.points_mut().retain(|(t, _)| *t < other_start && !tolerance.matches(*t, other_start));
+        } else {
+            other.points_mut().retain(|(t, _)| *t < start && !tolerance.matches(*t, start));
+        }
+        let other_points = std::mem::take(other.points_mut());
+        let points = self.points_mut();
+        points.extend(other_points);
+        points.sort_by(|(t1, _), (t2, _)| t1.total_cmp(t2));
+    }
+}
+
+/// Guesses the variable and reduction of a CarpetIOScalar file from its name, e.g. ("rho", "norm2")
+/// for "hydrobase-rho.norm2.asc"
+pub fn scalar_file_kind(filename: &str) -> Option<(&str, &str)> {
+    let stem = strip_compression_extension(filename).strip_suffix(".asc")?;
+    let (stem, reduction) = stem.rsplit_once('.')?;
+    if !REDUCTIONS.contains(&reduction) {
+        return None;
+    }
+    // Without the directory and the thorn of the group, if there is one
+    let name = stem.rsplit('/').next().unwrap_or(stem);
+    let variable = name.rsplit('-').next().unwrap_or(name);
+    Some((variable, reduction))
+}
+
+/// Whether a file holds time series rather than lines, e.g. "rho.scalars.asc"
+pub fn is_scalar_file(filename: &str) -> bool {
+    scalar_file_kind(filename).is_some()
+}
+
+/// Reads the time series of a CarpetIOScalar file, decompressing it first if needed, along with any
+/// problems found in it
+pub fn read_scalar_file(filename: &str, settings: &LoadSettings) -> Result<(Vec<TimeSeries>, LineProblems)> {
+    use crate::compression::open_file;
+
+    let (variable, reduction) = scalar_file_kind(filename).ok_or("not a file of scalar output")?;
+    let (_, reader) = open_file(filename)?;
+    let mut problems = LineProblems::new();
+    let mut selector = StepSelector::new(settings.time_filter, settings.time_tolerance);
+    let time_series = read_scalar_rows(reader, variable, reduction, &settings.columns, &mut selector, &mut problems)?;
+    Ok((time_series, problems))
+}
+
+/// Parses a list like "1:iteration 2:time 3:data" into column numbers and names
+fn parse_column_list(list: &str) -> Vec<(usize, String)> {
+    list.split_whitespace()
+        .filter_map(|entry| entry.split_once(':'))
+        .filter_map(|(index, name)| index.parse().ok().map(|i| (i, name.to_string())))
+        .collect()
+}
+
+/// Reads the rows of a CarpetIOScalar file, one for each iteration, into a time series for each data
+/// column. Its comment header announces the columns, e.g. `# 1:iteration 2:time 3:data` and
+/// `# data columns: 3:minimum 4:maximum 5:norm1`. The data columns of a "scalars" file are
+/// reductions of the variable, those of other files are variables of a group. Unless selected
+/// otherwise, the time is read from the "time" column and every data column is read.
+pub fn read_scalar_rows<R: BufRead>(reader: R, variable: &str, reduction: &str, columns: &ColumnSelection,
+                                    selector: &mut StepSelector, problems: &mut LineProblems) -> Result<Vec<TimeSeries>> {
+    let mut names: Vec<(usize, String)> = vec![(1, "iteration".to_string()), (2, "time".to_string())];
+    let mut data_columns: Vec<(usize, String)> = Vec::new();
+    let mut variable = variable.to_string();
+    let mut time_series: Vec<TimeSeries> = Vec::new();
+    let mut selected: Vec<usize> = Vec::new(); // column numbers of the time series
+    let mut time_column = 2;
+    let mut line_number = 0;
+
+    for l in reader.lines() {
+        let line = l?;
+        line_number += 1;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(comment) = line.strip_prefix('#') {
+            let comment = comment.trim();
+            if let Some(list) = comment.strip_prefix("data columns:") {
+                data_columns = parse_column_list(list);
+            } else if comment.starts_with("1:") {
+                names = parse_column_list(comment);
+            } else if let Some((_, name)) = comment.split_once("::") {
+                // The full name of the variable, e.g. "HYDROBASE::rho (hydrobase-rho)"
+                variable = name.split_whitespace().next().unwrap_or(name).to_string();
+            }
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        // The columns are settled by the first row
+        if time_series.is_empty() {
+            if data_columns.is_empty() {
+                let first_data = names.iter().map(|(i, _)| *i).filter(|i| *i > 2).min().unwrap_or(3);
+                let several = fields.len() > first_data;
+                data_columns = (first_data..=fields.len())
+                    .map(|i| (i, if several { format!("column {}", i) } else { variable.clone() }))
+                    .collect();
+            }
+            let number_of = |column: &Column| match column {
+                Column::Number(number) => Some(*number),
+                Column::Name(name) => names.iter().chain(&data_columns).find(|(_, n)| n == name).map(|(i, _)| *i),
+            };
+            time_column = columns.time.as_ref().and_then(number_of)
+                .or_else(|| names.iter().find(|(_, n)| n == "time").map(|(i, _)| *i))
+                .unwrap_or(2);
+            let wanted: Vec<usize> = columns.y.iter().filter_map(number_of).collect();
+            for (number, name) in &data_columns {
+                if !wanted.is_empty() && !wanted.contains(number) {
+                    continue;
+                }
+                // A column of a "scalars" file may also name its variable, e.g. "rho(minimum)"
+                let column_name = name.trim_end_matches(')');
+                let series = match (reduction, column_name.split_once('(')) {
+                    ("scalars", Some((variable, reduction))) => TimeSeries::new(variable, reduction),
+                    ("scalars", None) => TimeSeries::new(&variable, name),
+                    (_, Some((variable, _))) => TimeSeries::new(variable, reduction),
+                    (_, None) if name == "data" => TimeSeries::new(&variable, reduction),
+                    (_, None) => TimeSeries::new(name, reduction),
+                };
+                time_series.push(series);
+                selected.push(*number);
+            }
+            if time_series.is_empty() {
+                break;
+            }
+        }
+
+        let value = |number: usize| number.checked_sub(1).and_then(|i| fields.get(i)).and_then(|f| f.parse::<f64>().ok());
+        let time = match value(time_column) {
+            Some(t) if t.is_finite() => t,
+            _ => {
+                problems.push((line_number, ReadProblem::MalformedLine));
+                continue;
+            },
+        };
+        if !selector.keeps(time) {
+            continue;
+        }
+        match selected.iter().map(|number| value(*number)).collect::<Option<Vec<f64>>>() {
+            Some(values) => {
+                // A missing value, e.g. of a norm after the simulation blew up, is left out
+                for (series, y) in time_series.iter_mut().zip(values).filter(|(_, y)| !y.is_nan()) {
+                    series.points_mut().push((time, y));
+                }
+            },
+            None => problems.push((line_number, ReadProblem::MalformedLine)),
+        }
+    }
+    mark_truncated(problems, line_number);
+
+    for series in time_series.iter_mut() {
+        series.points_mut().sort_by(|(t1, _), (t2, _)| t1.total_cmp(t2));
+    }
+    Ok(time_series)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(text: &str, reduction: &str) -> (Vec<TimeSeries>, LineProblems) {
+        let mut problems = LineProblems::new();
+        let columns = LoadSettings::new().columns;
+        let mut selector = StepSelector::all();
+        let time_series = read_scalar_rows(text.as_bytes(), "rho", reduction, &columns, &mut selector, &mut problems).unwrap();
+        (time_series, problems)
+    }
+
+    #[test]
+    fn rows_without_a_finite_time_are_malformed() {
+        let text = "# 1:iteration 2:time 3:data\n2 1.0 5.0\n1 nan 4.0\n3 inf 6.0\n0 0.0 3.0\n";
+        let (time_series, problems) = read(text, "maximum");
+        assert_eq!(time_series.len(), 1);
+        assert_eq!(time_series[0].name, "rho (maximum)");
+        assert_eq!(time_series[0].points(), &[(0.0, 3.0), (1.0, 5.0)]);
+        assert_eq!(problems, vec![(3, ReadProblem::MalformedLine), (4, ReadProblem::MalformedLine)]);
+    }
+
+    #[test]
+    fn missing_values_are_left_out() {
+        let text = "# 1:iteration 2:time\n# data columns: 3:maximum 4:norm2\n0 0.0 1.0 2.0\n1 1.0 nan 3.0\n";
+        let (time_series, problems) = read(text, "scalars");
+        assert!(problems.is_empty());
+        assert_eq!(time_series[0].points(), &[(0.0, 1.0)]);
+        assert_eq!(time_series[1].points(), &[(0.0, 2.0), (1.0, 3.0)]);
+    }
+
+    #[test]
+    fn plots_share_the_points() {
+        let (mut time_series, _) = read("# 1:iteration 2:time 3:data\n0 0.0 1.0\n", "maximum");
+        let series_slice = time_series[0].series_slice();
+        assert!(Arc::ptr_eq(&series_slice.slice, &time_series[0].series_slice().slice));
+
+        // Merging leaves the slice of a plot being drawn as it was
+        let (later, _) = read("# 1:iteration 2:time 3:data\n1 1.0 2.0\n", "maximum");
+        time_series[0].merge(later.into_iter().next().unwrap(), &TimeTolerance::new());
+        assert_eq!(series_slice.slice.datalines[0].points, vec![(0.0, 1.0)]);
+        assert_eq!(time_series[0].points(), &[(0.0, 1.0), (1.0, 2.0)]);
+    }
+}
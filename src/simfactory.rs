@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+
+/// File extensions of the data files looked for in a simulation, before any compression extension
+const DATA_EXTENSIONS: [&str; 9] = ["xg", "yg", "zg", "dg", "asc", "csv", "tsv", "dat", "gp"];
+
+/// Files in the segments of a simulation are looked for this many directories deep
+const MAX_DEPTH: usize = 4;
+
+/// A variable of a simulation, i.e. a data file found in one or more of its segments
+#[derive(Debug, Clone)]
+pub struct Variable {
+    pub name: String, // path of the file relative to its segment, e.g. "SIMNAME/rho.x.asc"
+    pub files: Vec<String>, // the file in each segment that has it, in the order of the segments
+}
+
+/// The output-NNNN directories of a SimFactory simulation directory, in order
+pub fn segments(directory: &Path) -> Vec<PathBuf> {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut segments: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && matches!(path.file_name(), Some(name) if is_segment_name(&name.to_string_lossy())))
+        .collect();
+    segments.sort();
+    segments
+}
+
+/// Whether a directory name is that of a segment, e.g. "output-0003"
+fn is_segment_name(name: &str) -> bool {
+    match name.strip_prefix("output-") {
+        Some(number) => !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()),
+        None => false,
+    }
+}
+
+pub fn is_simulation(directory: &Path) -> bool {
+    !segments(directory).is_empty()
+}
+
+fn is_data_file(path: &Path) -> bool {
+    use crate::compression::strip_compression_extension;
+
+    let name = path.to_string_lossy();
+    match Path::new(strip_compression_extension(&name)).extension() {
+        Some(extension) => DATA_EXTENSIONS.contains(&extension.to_string_lossy().as_ref()),
+        None => false,
+    }
+}
+
+/// Collects the data files below a directory, as paths relative to it
+fn find_data_files(directory: &Path, relative: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+    let entries = match std::fs::read_dir(directory.join(relative)) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = relative.join(entry.file_name());
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+        if file_type.is_dir() && depth < MAX_DEPTH {
+            find_data_files(directory, &path, depth + 1, files);
+        } else if file_type.is_file() && is_data_file(&path) {
+            files.push(path);
+        }
+    }
+}
+
+/// Finds the variables of a simulation across all of its segments. Restarted runs usually write
+/// the same files in each segment, so each variable can be loaded stitched together from them.
+pub fn discover_variables(directory: &Path) -> Vec<Variable> {
+    let mut variables: Vec<Variable> = Vec::new();
+
+    for segment in segments(directory) {
+        let mut files = Vec::new();
+        find_data_files(&segment, Path::new(""), 0, &mut files);
+        files.sort();
+        for relative in files {
+            let name = relative.display().to_string();
+            let filename = segment.join(&relative).display().to_string();
+            match variables.iter_mut().find(|v| v.name == name) {
+                Some(variable) => variable.files.push(filename),
+                None => variables.push(Variable {
+                    name,
+                    files: vec![filename],
+                }),
+            }
+        }
+    }
+
+    variables.sort_by(|v1, v2| v1.name.cmp(&v2.name));
+    variables
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, write};
+
+    /// A simulation directory with two segments, made out of order, and things that are not segments
+    fn simulation(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("muninn-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        for segment in &["output-0001", "output-0000"] {
+            create_dir_all(directory.join(segment).join("SIM")).unwrap();
+            write(directory.join(segment).join("SIM/rho.x.asc"), "").unwrap();
+            write(directory.join(segment).join("SIM/notes.txt"), "").unwrap();
+        }
+        write(directory.join("output-0000/SIM/press.xg"), "").unwrap();
+        write(directory.join("output-0001/SIM/psi.xg.gz"), "").unwrap();
+        create_dir_all(directory.join("output-0002-active")).unwrap();
+        write(directory.join("output-0003"), "").unwrap();
+        directory
+    }
+
+    #[test]
+    fn segments_are_found_in_order() {
+        let directory = simulation("segments");
+        let segments = segments(&directory);
+        assert_eq!(segments, vec![directory.join("output-0000"), directory.join("output-0001")]);
+        assert!(is_simulation(&directory));
+        assert!(!is_simulation(&segments[0]));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn variables_are_collected_across_segments() {
+        let directory = simulation("variables");
+        let variables = discover_variables(&directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let names: Vec<&str> = variables.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(names, vec!["SIM/press.xg", "SIM/psi.xg.gz", "SIM/rho.x.asc"]);
+        // A file written in each segment is one variable, with its files in the order of the segments
+        let segment_file = |segment: &str, name: &str| directory.join(segment).join(name).display().to_string();
+        assert_eq!(variables[2].files, vec![segment_file("output-0000", "SIM/rho.x.asc"),
+                                            segment_file("output-0001", "SIM/rho.x.asc")]);
+        assert_eq!(variables[1].files, vec![segment_file("output-0001", "SIM/psi.xg.gz")]);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::data::{DataLine, Time};
+use std::convert::TryInto;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Lines received, each with the name of its series
+pub type ReceivedLines = Vec<(String, Time, DataLine)>;
+
+/// Frames longer than this are taken to be garbage, and end the connection
+const MAX_FRAME_LENGTH: u32 = 1 << 30;
+
+/// Where to listen: a Unix domain socket, or a TCP port on localhost
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddress {
+    Unix(PathBuf),
+    Tcp(u16),
+}
+
+impl ListenAddress {
+    /// A port number is taken as a TCP port, anything else as the path of a Unix socket
+    pub fn parse(address: &str) -> ListenAddress {
+        match address.trim().parse::<u16>() {
+            Ok(port) => ListenAddress::Tcp(port),
+            Err(_) => ListenAddress::Unix(PathBuf::from(address)),
+        }
+    }
+}
+
+/// Receives lines pushed by a running simulation, without going through the filesystem. Any number
+/// of clients may connect, each sending frames of one line of a series at some time:
+///
+/// - u32: length of the rest of the frame in bytes
+/// - f64: time
+/// - u32: length of the series name in bytes, followed by the name in UTF-8
+/// - u32: number of points n, followed by n x values and then n y values as f64
+///
+/// All numbers are little-endian. A connection is closed on a malformed frame, including one whose
+/// time is not a finite number.
+pub struct SocketListener {
+    pub address: ListenAddress,
+    receiver: Receiver<(String, Time, DataLine)>,
+}
+
+impl SocketListener {
+    /// Starts listening in the background. Fails if the address can not be bound.
+    pub fn bind(address: ListenAddress) -> Result<SocketListener> {
+        use std::net::TcpListener;
+        use std::os::unix::net::UnixListener;
+
+        let (sender, receiver) = channel();
+        match &address {
+            ListenAddress::Unix(path) => {
+                remove_stale_socket(path);
+                let listener = UnixListener::bind(path)?;
+                std::thread::spawn(move || {
+                    for stream in listener.incoming().filter_map(|s| s.ok()) {
+                        serve(stream, sender.clone());
+                    }
+                });
+            },
+            ListenAddress::Tcp(port) => {
+                let listener = TcpListener::bind(("127.0.0.1", *port))?;
+                std::thread::spawn(move || {
+                    for stream in listener.incoming().filter_map(|s| s.ok()) {
+                        serve(stream, sender.clone());
+                    }
+                });
+            },
+        }
+
+        Ok(SocketListener {
+            address,
+            receiver,
+        })
+    }
+
+    /// Takes the lines received since the last call
+    pub fn receive(&self) -> ReceivedLines {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for SocketListener {
+    fn drop(&mut self) {
+        if let ListenAddress::Unix(path) = &self.address {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// A socket left behind by an earlier run would keep the address from being bound. Only sockets
+/// are removed, never other files.
+fn remove_stale_socket(path: &std::path::Path) {
+    use std::os::unix::fs::FileTypeExt;
+
+    if matches!(std::fs::symlink_metadata(path), Ok(metadata) if metadata.file_type().is_socket()) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// Reads the frames of one connection in a thread of its own, until it is closed
+fn serve<S: Read + Send + 'static>(mut stream: S, sender: Sender<(String, Time, DataLine)>) {
+    std::thread::spawn(move || {
+        loop {
+            match read_frame(&mut stream) {
+                Ok(Some(line)) => if sender.send(line).is_err() {
+                    return;
+                },
+                Ok(None) => return,
+                Err(e) => {
+                    eprintln!("Closing connection: {}", e);
+                    return;
+                },
+            }
+        }
+    });
+}
+
+/// Reads one frame. Returns None if the connection was closed between frames.
+fn read_frame<R: Read>(reader: &mut R) -> Result<Option<(String, Time, DataLine)>> {
+    let mut length = [0; 4];
+    match reader.read_exact(&mut length) {
+        Ok(()) => {},
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let length = u32::from_le_bytes(length);
+    if length > MAX_FRAME_LENGTH {
+        return Err(format!("frame of {} bytes is too long", length).into());
+    }
+    let mut frame = vec![0; length as usize];
+    reader.read_exact(&mut frame)?;
+
+    let mut frame = FrameReader { rest: &frame };
+    let time = frame.f64()?;
+    if !time.is_finite() {
+        return Err(format!("frame time {} is not a finite number", time).into());
+    }
+    let name_length = frame.u32()? as usize;
+    let name = String::from_utf8(frame.bytes(name_length)?.to_vec())?;
+    let n_points = frame.u32()? as usize;
+    let xs = frame.bytes(n_points.saturating_mul(8))?;
+    let ys = frame.bytes(n_points.saturating_mul(8))?;
+    if !frame.rest.is_empty() {
+        return Err("frame is longer than its contents".into());
+    }
+
+    let value = |bytes: &[u8]| f64::from_le_bytes(bytes.try_into().unwrap());
+    let points = xs.chunks_exact(8).zip(ys.chunks_exact(8))
+        .map(|(x, y)| (value(x), value(y)))
+        .filter(|(x, y)| !x.is_nan() && !y.is_nan())
+        .collect();
+    let name = if name.is_empty() { "socket".to_string() } else { name };
+    Ok(Some((name, time, DataLine {
+        points,
+        channels: Vec::new(),
+        metadata: None,
+    })))
+}
+
+/// Takes values from the front of a frame
+struct FrameReader<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> FrameReader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8]> {
+        if n > self.rest.len() {
+            return Err("frame is shorter than its contents".into());
+        }
+        let (bytes, rest) = self.rest.split_at(n);
+        self.rest = rest;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into()?))
+    }
+
+    fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.bytes(8)?.try_into()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A frame as a client would send it
+    fn frame(time: Time, name: &str, points: &[(f64, f64)]) -> Vec<u8> {
+        let mut contents = time.to_le_bytes().to_vec();
+        contents.extend((name.len() as u32).to_le_bytes());
+        contents.extend(name.as_bytes());
+        contents.extend((points.len() as u32).to_le_bytes());
+        contents.extend(points.iter().flat_map(|(x, _)| x.to_le_bytes()));
+        contents.extend(points.iter().flat_map(|(_, y)| y.to_le_bytes()));
+        let mut frame = (contents.len() as u32).to_le_bytes().to_vec();
+        frame.extend(contents);
+        frame
+    }
+
+    #[test]
+    fn frames_are_read_one_after_the_other() {
+        let mut bytes = frame(1.5, "rho", &[(0.0, 1.0), (1.0, f64::NAN), (2.0, 3.0)]);
+        bytes.extend(frame(2.0, "", &[]));
+        let mut reader = bytes.as_slice();
+
+        let (name, time, line) = read_frame(&mut reader).unwrap().unwrap();
+        assert_eq!((name.as_str(), time), ("rho", 1.5));
+        assert_eq!(line.points, vec![(0.0, 1.0), (2.0, 3.0)]);
+        let (name, time, line) = read_frame(&mut reader).unwrap().unwrap();
+        assert_eq!((name.as_str(), time), ("socket", 2.0));
+        assert!(line.points.is_empty());
+        assert!(read_frame(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn truncated_frames_are_errors() {
+        let bytes = frame(1.5, "rho", &[(0.0, 1.0)]);
+        assert!(read_frame(&mut &bytes[..bytes.len() - 1]).is_err());
+
+        // A length that does not match the contents
+        let mut bytes = frame(1.5, "rho", &[(0.0, 1.0)]);
+        let length = bytes.len() as u32 - 4;
+        bytes[..4].copy_from_slice(&(length - 8).to_le_bytes());
+        assert!(read_frame(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn oversized_frames_are_errors() {
+        let bytes = (MAX_FRAME_LENGTH + 1).to_le_bytes();
+        assert!(read_frame(&mut bytes.as_slice()).is_err());
+
+        // A number of points beyond the frame
+        let mut bytes = frame(1.5, "rho", &[]);
+        let n_points = bytes.len() - 4;
+        bytes[n_points..].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(read_frame(&mut bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn frames_without_a_finite_time_are_errors() {
+        for time in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY].iter() {
+            assert!(read_frame(&mut frame(*time, "rho", &[(0.0, 1.0)]).as_slice()).is_err());
+        }
+    }
+
+    #[test]
+    fn addresses_are_ports_or_socket_paths() {
+        assert_eq!(ListenAddress::parse("4242"), ListenAddress::Tcp(4242));
+        assert_eq!(ListenAddress::parse(" 80 "), ListenAddress::Tcp(80));
+        assert_eq!(ListenAddress::parse("70000"), ListenAddress::Unix(PathBuf::from("70000")));
+        assert_eq!(ListenAddress::parse("/tmp/muninn.sock"), ListenAddress::Unix(PathBuf::from("/tmp/muninn.sock")));
+    }
+}
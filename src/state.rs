@@ -1,5 +1,8 @@
-use crate::data::{Data, DataSlice};
+use crate::data::{Data, LoadReport, LoadSettings, SeriesSlice};
+use crate::plane::PlaneSeriesSlice;
 use crate::plotting::{PlotRange, PlotSettings};
+use crate::socket::{ListenAddress, SocketListener};
+use std::path::PathBuf;
 use std::time::Instant;
 use std::sync::{Arc, Mutex};
 use gdk_pixbuf::Pixbuf;
@@ -18,8 +21,16 @@ pub struct State {
     pub update_interval: i32, // in ms
     pub timestep_interval: usize, // allows skipping timesteps
     pub loaded_data: Option<Data>, // Currently loaded dataset
-    pub current_slice: Option<DataSlice>, // Slice for current timestep
+    pub load_settings: LoadSettings, // Options for reading files
+    pub load_report: Option<LoadReport>, // Problems from the last load, until they have been shown
+    pub pending_simulation: Option<PathBuf>, // Simulation directory to pick variables from once the window is up
+    pub listener: Option<SocketListener>, // Receives lines pushed by a running simulation
+    pub current_slices: Option<Vec<SeriesSlice>>, // Slices of all series for current timestep, shared with the data
+    pub current_planes: Vec<PlaneSeriesSlice>, // Slices of all plane series for current timestep
+    pub show_time_series: bool, // Whether to plot the time series instead of the current step
     pub is_playing: bool, // Whether the plot is being animated
+    pub follow_files: bool, // Whether to watch loaded files for appended data
+    pub follow_jump_to_newest: bool, // Whether to jump to the newest step when files have grown
     last_step_made_at: Instant, // time when last frame was rendered
     pub update_needed: bool, // whether the current image needs to be updated
     pub plot_settings: PlotSettings,
@@ -41,8 +52,16 @@ impl State {
             update_interval: 100,
             timestep_interval: 1,
             loaded_data: None,
-            current_slice: None,
+            load_settings: LoadSettings::new(),
+            load_report: None,
+            pending_simulation: None,
+            listener: None,
+            current_slices: None,
+            current_planes: Vec::new(),
+            show_time_series: false,
             is_playing: false,
+            follow_files: false,
+            follow_jump_to_newest: false,
             last_step_made_at: Instant::now(),
             update_needed: true,
             // plot_range_setting: PlotRange::Auto,
@@ -56,6 +75,47 @@ impl State {
         }
     }
 
+    /// Loads data from files, replacing the current data if anything could be read. Problems with
+    /// the files are printed to stderr and kept for the UI to show.
+    pub fn load_files(&mut self, filenames: Vec<String>) {
+        let (data, report) = Data::from_files(filenames, &self.load_settings);
+        if let Some(data) = data {
+            self.load_data(data);
+        }
+        if !report.is_empty() {
+            eprint!("While loading files:\n{}", report);
+            self.load_report = Some(report);
+        }
+    }
+
+    /// Loads the files of the segments of a simulation, keeping only the newest segment where a
+    /// restart overlaps an older one, whether or not restarts are merged otherwise
+    pub fn load_simulation(&mut self, filenames: Vec<String>) {
+        let merge_restarts = self.load_settings.merge_restarts;
+        self.load_settings.merge_restarts = true;
+        self.load_files(filenames);
+        self.load_settings.merge_restarts = merge_restarts;
+    }
+
+    /// Adds data from more files to the loaded data, keeping the current time and view. Loads
+    /// the files as usual if nothing is loaded yet.
+    pub fn add_files(&mut self, filenames: Vec<String>) {
+        if self.loaded_data.is_none() {
+            return self.load_files(filenames);
+        }
+        let (data, mut report) = Data::from_files(filenames, &self.load_settings);
+        if let (Some(data), Some(loaded_data)) = (data, &mut self.loaded_data) {
+            let overlaps = loaded_data.merge(data, &self.load_settings);
+            report.restarts.extend(overlaps);
+            self.update_times();
+            self.go_to_step(self.current_step);
+        }
+        if !report.is_empty() {
+            eprint!("While adding files:\n{}", report);
+            self.load_report = Some(report);
+        }
+    }
+
     pub fn load_data(&mut self, data: Data) {
         let times = data.times();
         
@@ -67,11 +127,81 @@ impl State {
         self.n_steps = times.len();
         self.times = times;
         self.current_time = current_time;
-        self.current_slice = Some(data.at_time(current_time));
+        self.current_slices = Some(data.at_time(current_time));
+        self.current_planes = data.planes_at_time(current_time);
         self.loaded_data = Some(data);
     }
 
+    /// Reads data appended to the loaded files, if following them, and data that arrived through
+    /// streams or the listener, keeping the view as it is. Returns the time of the displayed step if
+    /// anything changed, None otherwise.
+    pub fn follow_loaded_files(&mut self) -> Option<f64> {
+        let received = self.receive_from_listener();
+        let settings = self.load_settings.clone();
+        let follow_files = self.follow_files;
+        let updated = match &mut self.loaded_data {
+            Some(d) => {
+                let streamed = d.receive_streamed();
+                let appended = follow_files && d.update_from_files(&settings);
+                streamed || appended
+            },
+            None => false,
+        };
+        if !updated && !received {
+            return None;
+        }
+
+        self.update_times();
+        if self.follow_jump_to_newest {
+            self.go_to_last_step()
+        } else {
+            self.go_to_step(self.current_step)
+        }
+    }
+
+    /// Starts listening for lines sent by a running simulation, see `SocketListener`
+    pub fn listen(&mut self, address: &str) -> Result<(), String> {
+        let address = ListenAddress::parse(address);
+        let listener = SocketListener::bind(address.clone())
+            .map_err(|e| format!("Could not listen on {:?}: {}", address, e))?;
+        self.listener = Some(listener);
+        Ok(())
+    }
+
+    /// Adds the lines sent to the listener since the last call, starting out with empty data if
+    /// nothing is loaded. Returns whether there were any.
+    fn receive_from_listener(&mut self) -> bool {
+        let lines = match &self.listener {
+            Some(listener) => listener.receive(),
+            None => return false,
+        };
+        if lines.is_empty() {
+            return false;
+        }
+        if self.loaded_data.is_none() {
+            self.load_data(Data::empty(&self.load_settings));
+        }
+        match &mut self.loaded_data {
+            Some(d) => d.add_lines(lines),
+            None => false,
+        }
+    }
+
+    /// Updates the list of steps from the loaded data, keeping the current step at the current time
+    fn update_times(&mut self) {
+        if let Some(d) = &self.loaded_data {
+            let times = d.times();
+            self.current_step = times.partition_point(|t| *t <= self.current_time).saturating_sub(1);
+            self.n_steps = times.len();
+            self.times = times;
+        }
+    }
+
     pub fn advance_animation(&mut self) {
+        // Streams may not have delivered any steps yet
+        if self.n_steps == 0 {
+            return;
+        }
         let now = Instant::now();
         let time_since_last_step = now.duration_since(self.last_step_made_at);
         if time_since_last_step.as_millis() > self.update_interval as u128 {
@@ -82,7 +212,11 @@ impl State {
         }
     }
 
+    /// Set state to the step closest to a time. Returns the time at that step. Returns None if no steps are loaded.
     pub fn jump_to_time(&mut self, time: f64) -> Option<f64> {
+        if self.n_steps == 0 {
+            return None;
+        }
         if let Some(d) = &self.loaded_data {
             // clamp target time to available data
             let mut target_time = time;
@@ -95,9 +229,9 @@ impl State {
 
             // find correct target step
             // We also 'correct' target_time to where we actually jump to
-            let target_step = self.times.iter().rposition(|t| target_time >= *t).unwrap();
+            let target_step = self.times.partition_point(|t| *t <= target_time).saturating_sub(1);
 
-            if target_step < self.times.len() {
+            if target_step + 1 < self.times.len() {
                 let time_before_target = self.times[target_step];
                 let time_after_target = self.times[target_step+1];
                 target_time = if (target_time - time_before_target) < (time_after_target - target_time) {
@@ -105,10 +239,13 @@ impl State {
                 } else {
                     self.times[target_step+1]
                 }
+            } else {
+                target_time = self.times[target_step];
             }
 
             self.current_step = target_step;
-            self.current_slice = Some(d.at_time(target_time));
+            self.current_slices = Some(d.at_time(target_time));
+            self.current_planes = d.planes_at_time(target_time);
             self.current_time = target_time;
             self.update_needed = true;
             return Some(target_time);
@@ -118,6 +255,9 @@ impl State {
 
     /// Set state to a specified time steps. Returns time at that step. Returns None if no steps are loaded.
     pub fn go_to_step(&mut self, step: usize) -> Option<f64> {
+        if self.n_steps == 0 {
+            return None;
+        }
         if let Some(d) = &self.loaded_data {
             let mut target_step = step;
             if target_step >= self.n_steps {
@@ -125,7 +265,8 @@ impl State {
             }
             self.current_step = target_step;
             let target_time = self.times[target_step];
-            self.current_slice = Some(d.at_time(target_time));
+            self.current_slices = Some(d.at_time(target_time));
+            self.current_planes = d.planes_at_time(target_time);
             self.current_time = target_time;
             self.update_needed = true;
             Some(target_time)
@@ -165,10 +306,62 @@ impl State {
         self.go_to_step(target_step)
     }
 
+    /// Whether the plot shows a heatmap of planes rather than lines
+    pub fn shows_heatmap(&self) -> bool {
+        !self.current_planes.is_empty() && !self.shows_time_series()
+    }
+
+    /// Whether the plot shows time series against time, which it does if asked for or if they are
+    /// all that is loaded
+    pub fn shows_time_series(&self) -> bool {
+        match &self.loaded_data {
+            Some(d) if !d.time_series.is_empty() => {
+                self.show_time_series || (d.series.is_empty() && d.planes.is_empty() && d.on_demand.is_none() && d.streams.is_empty())
+            },
+            _ => false,
+        }
+    }
+
+    /// Plots the current step in the background. If there are planes, the first of them is shown as
+    /// a heatmap instead of the lines. Time series are shown instead of either if asked for.
     pub fn request_plot(&mut self, status_mutex: Arc<Mutex<PlotStatus>>) -> bool {
-        use crate::plotting::plot_data_slice_to_svg;
+        use crate::heatmap::plot_plane_slice_to_svg;
+        use crate::plotting::{plot_data_slice_to_svg, plot_time_series_to_svg};
+        if let (true, Some(d)) = (self.shows_time_series(), &self.loaded_data) {
+            let time_series: Vec<SeriesSlice> = d.time_series.iter().map(|s| s.series_slice()).collect();
+            let time = self.current_time;
+            let settings = self.plot_settings.clone();
+            let size = self.plot_area_size;
+
+            rayon::spawn(move || {
+                {
+                    let mut status_locked = status_mutex.lock().unwrap();
+                    *status_locked = PlotStatus::Working;
+                }
+                let (string, ranges) = plot_time_series_to_svg(&time_series, time, &settings, &size);
+                let mut status_locked = status_mutex.lock().unwrap();
+                *status_locked = PlotStatus::Finished(Some((string, ranges)));
+            });
+            return true;
+        }
+        if let Some(plane) = self.current_planes.first() {
+            let plane = plane.clone();
+            let settings = self.plot_settings.clone();
+            let size = self.plot_area_size;
+
+            rayon::spawn(move || {
+                {
+                    let mut status_locked = status_mutex.lock().unwrap();
+                    *status_locked = PlotStatus::Working;
+                }
+                let (string, ranges) = plot_plane_slice_to_svg(&plane, &settings, &size);
+                let mut status_locked = status_mutex.lock().unwrap();
+                *status_locked = PlotStatus::Finished(Some((string, ranges)));
+            });
+            return true;
+        }
         if let Some(d) = &self.loaded_data {
-            let s = match &self.current_slice {
+            let s = match &self.current_slices {
                 None => {
                     d.at_time(self.current_time)
                 },
@@ -211,4 +404,58 @@ impl MouseState {
             drag_start: (0.0, 0.0),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A named pipe that is open for writing, but nothing has been written to yet. Dropping it
+    /// ends the stream and removes the pipe.
+    struct Fifo {
+        path: PathBuf,
+        _writer: std::fs::File,
+    }
+
+    impl Drop for Fifo {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+
+    /// A state reading only a named pipe
+    fn state_waiting_for_stream(name: &str) -> (State, Fifo) {
+        let path = std::env::temp_dir().join(format!("muninn-{}-{}.fifo", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let status = std::process::Command::new("mkfifo").arg(&path).status().expect("Failed to run mkfifo");
+        assert!(status.success());
+
+        let mut state = State::new();
+        state.load_files(vec![path.display().to_string()]);
+        // Waits for the thread reading the stream to open it
+        let writer = std::fs::OpenOptions::new().write(true).open(&path).unwrap();
+        (state, Fifo { path, _writer: writer })
+    }
+
+    #[test]
+    fn navigation_without_steps_does_nothing() {
+        let (mut state, fifo) = state_waiting_for_stream("navigation");
+        assert!(state.loaded_data.is_some());
+        assert_eq!(state.n_steps, 0);
+
+        assert_eq!(state.go_to_first_step(), None);
+        assert_eq!(state.go_to_next_step(), None);
+        assert_eq!(state.go_to_previous_step(), None);
+        assert_eq!(state.go_to_last_step(), None);
+        assert_eq!(state.jump_to_time(1.0), None);
+
+        state.is_playing = true;
+        state.update_interval = 0;
+        std::thread::sleep(Duration::from_millis(2));
+        state.advance_animation();
+        assert_eq!(state.current_step, 0);
+
+        drop(fifo);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::data::{DataLine, FileFormat, LineMetadata, LineProblems, LoadSettings, StepSelector, Time};
+use std::io::BufRead;
+use std::sync::mpsc::{channel, Receiver};
+use std::sync::{Arc, Mutex};
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+/// Lines of the blocks read in one go
+type Lines = Vec<(Time, DataLine)>;
+
+/// Name under which standard input is given, e.g. `zcat run.xg.gz | muninn -`
+pub const STDIN: &str = "-";
+
+/// Whether a file is read as a stream: standard input or a named pipe
+pub fn is_stream(filename: &str) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    filename == STDIN || matches!(std::fs::metadata(filename), Ok(metadata) if metadata.file_type().is_fifo())
+}
+
+/// Data arriving through a stream. A thread of its own reads the stream and passes on each block
+/// as soon as it is complete, so the data can be shown while it is still being written.
+#[derive(Clone)]
+pub struct StreamSource {
+    pub name: String, // for naming series of lines without a header
+    receiver: Arc<Mutex<Receiver<Lines>>>,
+}
+
+impl StreamSource {
+    pub fn new(filename: &str, settings: &LoadSettings) -> StreamSource {
+        let name = if filename == STDIN { "stdin" } else { filename }.to_string();
+        let (sender, receiver) = channel();
+
+        let thread_filename = filename.to_string();
+        let thread_name = name.clone();
+        let settings = settings.clone();
+        std::thread::spawn(move || {
+            // Reading stops once nobody is receiving anymore
+            let result = read_stream(&thread_filename, &settings, |pairs| sender.send(pairs).is_ok());
+            if let Err(e) = result {
+                eprintln!("Could not read {}: {}", thread_name, e);
+            }
+        });
+
+        StreamSource {
+            name,
+            receiver: Arc::new(Mutex::new(receiver)),
+        }
+    }
+
+    /// Takes the lines of all blocks that arrived since the last call
+    pub fn receive(&self) -> Lines {
+        let receiver = self.receiver.lock().unwrap();
+        receiver.try_iter().flatten().collect()
+    }
+}
+
+/// Reads a stream until it ends, handing on the lines of each complete block. Problems with lines
+/// are not reported, since a stream can not be looked at again anyway.
+fn read_stream<F>(filename: &str, settings: &LoadSettings, deliver: F) -> Result<()>
+    where F: FnMut(Lines) -> bool {
+    use crate::compression::{decompress, strip_compression_extension};
+    use std::fs::File;
+
+    // Opening a named pipe waits for a writer, which is why it is done here
+    let (_, mut reader) = if filename == STDIN {
+        decompress(std::io::stdin())?
+    } else {
+        decompress(File::open(filename)?)?
+    };
+    let format = FileFormat::detect(strip_compression_extension(filename), reader.fill_buf()?);
+    read_blocks(reader, format, filename, settings, deliver)
+}
+
+/// Reads blocks of the given format until the reader ends, handing on the lines of each
+fn read_blocks<R: BufRead, F>(reader: R, format: FileFormat, filename: &str, settings: &LoadSettings, mut deliver: F) -> Result<()>
+    where F: FnMut(Lines) -> bool {
+    let mut splitter = BlockSplitter::new(format);
+    let mut selector = StepSelector::new(settings.time_filter, settings.time_tolerance);
+    let mut metadata: Option<LineMetadata> = None;
+    let mut parse = |chunk: String| -> Result<Lines> {
+        let mut time_line_pairs = crate::data::read_datalines(chunk.as_bytes(), format, filename, settings,
+                                                              &mut selector, &mut LineProblems::new())?;
+        // Lines without a header take the latest one seen, like lines appended to a followed file
+        for (_, line) in time_line_pairs.iter_mut() {
+            match &line.metadata {
+                Some(m) => metadata = Some(m.clone()),
+                None => line.metadata = metadata.clone(),
+            }
+        }
+        Ok(time_line_pairs)
+    };
+
+    for line in reader.lines() {
+        if let Some(chunk) = splitter.push(&line?) {
+            let time_line_pairs = parse(chunk)?;
+            if !time_line_pairs.is_empty() && !deliver(time_line_pairs) {
+                return Ok(());
+            }
+        }
+    }
+    if let Some(chunk) = splitter.finish() {
+        let time_line_pairs = parse(chunk)?;
+        if !time_line_pairs.is_empty() {
+            deliver(time_line_pairs);
+        }
+    }
+    Ok(())
+}
+
+/// Cuts a stream into chunks of complete blocks, each ending at a blank line after points. A chunk
+/// that goes on with points right away is given the comments they belong to, e.g. the time of their
+/// block. Delimited files have no blocks, so they are passed on in one piece once the stream ends.
+struct BlockSplitter {
+    format: FileFormat,
+    header: String, // comments before the first points, describing the columns of Carpet files
+    comments: String, // the latest run of comments
+    in_comments: bool, // whether the latest non-blank line was a comment
+    seen_points: bool,
+    chunk: String,
+    has_points: bool, // whether the chunk has any points yet
+}
+
+impl BlockSplitter {
+    fn new(format: FileFormat) -> BlockSplitter {
+        BlockSplitter {
+            format,
+            header: String::new(),
+            comments: String::new(),
+            in_comments: false,
+            seen_points: false,
+            chunk: String::new(),
+            has_points: false,
+        }
+    }
+
+    /// Adds a line, returning a chunk of blocks if the line completed one
+    fn push(&mut self, line: &str) -> Option<String> {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            if self.has_points && !matches!(self.format, FileFormat::Delimited(_)) {
+                return self.take_chunk();
+            }
+            return None;
+        }
+
+        if trimmed.starts_with('#') || trimmed.starts_with('"') {
+            if !self.in_comments {
+                self.comments.clear();
+            }
+            self.comments.push_str(line);
+            self.comments.push('\n');
+            if !self.seen_points {
+                self.header.push_str(line);
+                self.header.push('\n');
+            }
+            self.in_comments = true;
+        } else {
+            // Column descriptions of Carpet files are only given once, at the start of the stream
+            if !self.has_points && matches!(self.format, FileFormat::CarpetAscii) {
+                self.chunk.insert_str(0, &self.header);
+            } else if self.chunk.is_empty() {
+                self.chunk = self.comments.clone();
+            }
+            self.in_comments = false;
+            self.seen_points = true;
+            self.has_points = true;
+        }
+        self.chunk.push_str(line);
+        self.chunk.push('\n');
+        None
+    }
+
+    /// The rest of the stream, once it ended
+    fn finish(&mut self) -> Option<String> {
+        if self.has_points {
+            self.take_chunk()
+        } else {
+            None
+        }
+    }
+
+    fn take_chunk(&mut self) -> Option<String> {
+        self.has_points = false;
+        Some(std::mem::take(&mut self.chunk))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The chunks a splitter makes of a stream, including the rest once it ended
+    fn split(format: FileFormat, text: &str) -> Vec<String> {
+        let mut splitter = BlockSplitter::new(format);
+        let mut chunks: Vec<String> = text.lines().filter_map(|line| splitter.push(line)).collect();
+        chunks.extend(splitter.finish());
+        chunks
+    }
+
+    #[test]
+    fn blocks_are_cut_at_blank_lines_after_points() {
+        let text = "\"Output of variable psi, x axis\n\n\"Time = 0\n0 1\n1 2\n\n\n\"Time = 1\n0 3\n";
+        let chunks = split(FileFormat::YGraph, text);
+        assert_eq!(chunks, vec![
+            "\"Output of variable psi, x axis\n\"Time = 0\n0 1\n1 2\n".to_string(),
+            // The last block is complete only once the stream ended
+            "\"Time = 1\n0 3\n".to_string(),
+        ]);
+
+        // Comments without points after them make no chunk
+        assert!(split(FileFormat::Gnuplot, "# time = 0\n\n# time = 1\n").is_empty());
+    }
+
+    #[test]
+    fn carpet_chunks_keep_the_column_descriptions() {
+        let header = "# 1D ASCII output created by CarpetIOASCII\n# data columns: 13:rho\n";
+        let text = format!("{}0 0 0 0 0 0 0 0 0.0 0 0 0 1\n\n0 0 0 0 0 0 0 0 1.0 0 0 0 2\n\n", header);
+        let chunks = split(FileFormat::CarpetAscii, &text);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|chunk| chunk.starts_with(header)));
+        assert!(chunks[1].ends_with("1.0 0 0 0 2\n"));
+    }
+
+    #[test]
+    fn delimited_streams_are_passed_on_whole() {
+        let chunks = split(FileFormat::Delimited(','), "t,x,y\n0,0,1\n\n1,0,2\n");
+        assert_eq!(chunks, vec!["t,x,y\n0,0,1\n1,0,2\n".to_string()]);
+    }
+
+    #[test]
+    fn blocks_are_delivered_as_they_arrive() {
+        let text = "\"Output of variable psi, x axis\n\"Time = 0\n0 1\n\n\"Time = 1\n0 2\n1 3\n";
+        let mut deliveries: Vec<Lines> = Vec::new();
+        read_blocks(text.as_bytes(), FileFormat::YGraph, "test", &LoadSettings::new(), |pairs| {
+            deliveries.push(pairs);
+            true
+        }).unwrap();
+
+        assert_eq!(deliveries.len(), 2);
+        let (time, line) = &deliveries[1][0];
+        assert_eq!(*time, 1.0);
+        assert_eq!(line.points, vec![(0.0, 2.0), (1.0, 3.0)]);
+        // The block after the header still belongs to its variable
+        assert_eq!(line.metadata.as_ref().map(|m| m.variable.as_str()), Some("psi"));
+    }
+
+    #[test]
+    fn reading_stops_once_nobody_receives() {
+        let text = "\"Time = 0\n0 1\n\n\"Time = 1\n0 2\n\n\"Time = 2\n0 3\n";
+        let mut deliveries = 0;
+        read_blocks(text.as_bytes(), FileFormat::YGraph, "test", &LoadSettings::new(), |_| {
+            deliveries += 1;
+            false
+        }).unwrap();
+        assert_eq!(deliveries, 1);
+    }
+}
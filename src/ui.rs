@@ -3,7 +3,7 @@ use glib::timeout_add_local;
 use std::time::Duration;
 use std::sync::{Arc, Mutex};
 
-use gtk::{Application, ApplicationWindow, Builder, Button, DrawingArea, Entry, EventBox, FileChooserDialog, Image, SpinButton, ToggleButton, Viewport};
+use gtk::{Application, ApplicationWindow, Builder, Button, CheckButton, ComboBoxText, DrawingArea, Entry, EventBox, FileChooserDialog, Image, MessageDialog, SpinButton, ToggleButton, Viewport};
 use gtk::ResponseType;
 use gtk::prelude::*;
 use std::rc::Rc;
@@ -11,8 +11,10 @@ use std::cell::RefCell;
 use glib::clone;
 
 use crate::state::{State, PlotStatus};
-use crate::data::Data;
-use crate::plotting::PlotRange;
+use crate::contour::ContourLevels;
+use crate::data::{Column, ColumnSelection, LoadSettings, to_string_gnuplot};
+use crate::heatmap::COLORBAR_AREA_SIZE;
+use crate::plotting::{ComplexPart, PlotRange, UncertaintyStyle, X_LABEL_AREA_SIZE, Y_LABEL_AREA_SIZE};
 
 
 use gdk_pixbuf::{Pixbuf, PixbufLoader};
@@ -54,10 +56,19 @@ pub fn build_ui(application: &Application, state_cell: Rc<RefCell<State>>) {
     let (autoscale_x_toggle, autoscale_y_toggle) = setup_autoscale_toggles(builder.clone(), state_cell.clone());
 
     // Logscale toggle setup
-    let (_logscale_x_toggle, _logscale_y_toggle) = setup_logscale_toggles(builder.clone(), state_cell.clone());
+    let (_logscale_x_toggle, _logscale_y_toggle, _logscale_color_toggle) = setup_logscale_toggles(builder.clone(), state_cell.clone());
 
     // Line toggle setup
-    let (_line_toggle, _point_toggle, _color_toggle) = setup_style_toggles(builder.clone(), state_cell.clone());
+    let (_line_toggle, _point_toggle, _color_toggle, _composite_toggle) = setup_style_toggles(builder.clone(), state_cell.clone());
+
+    // Complex part and uncertainty style setup
+    let (_complex_part_combo, _uncertainty_combo) = setup_channel_combos(builder.clone(), state_cell.clone());
+
+    // Contour levels and heatmap toggle setup
+    let (_contour_levels_entry, _heatmap_toggle) = setup_contour_widgets(builder.clone(), state_cell.clone());
+
+    // Time series toggle setup
+    let _time_series_toggle = setup_time_series_toggle(builder.clone(), state_cell.clone());
 
     // Plot range entry setup
     let (x_min_entry, x_max_entry, y_min_entry, y_max_entry) = setup_plot_range_entries(builder.clone(), state_cell.clone(), (autoscale_x_toggle, autoscale_y_toggle));
@@ -65,8 +76,12 @@ pub fn build_ui(application: &Application, state_cell: Rc<RefCell<State>>) {
     // Plot Image setup
     let plot_area = setup_plot_area(builder.clone(), state_cell.clone());
 
-    // Load button setup
+    // Load and add button setup
     let _load_button = setup_load_button(builder.clone(), state_cell.clone(), window.clone());
+    let _add_button = setup_add_button(builder.clone(), state_cell.clone(), window.clone());
+
+    // Open simulation button setup
+    let _open_simulation_button = setup_open_simulation_button(builder.clone(), state_cell.clone(), window.clone());
 
     // Save button setup
     let _save_button = setup_save_button(builder.clone(), state_cell.clone(), window.clone());
@@ -74,9 +89,22 @@ pub fn build_ui(application: &Application, state_cell: Rc<RefCell<State>>) {
     // export_gnuplot_button setup
     let _export_gnuplot_button = setup_export_gnuplot_button(builder.clone(), state_cell.clone(), window.clone());
 
+    // Follow toggles setup
+    let (_follow_toggle, _follow_jump_toggle) = setup_follow_toggles(builder.clone(), state_cell.clone());
+
+    // Check followed files for new data (called every second)
+    timeout_add_local(Duration::from_secs(1), clone!(@strong state_cell,
+                                                     @strong current_time_entry => move || {
+        let time = state_cell.borrow_mut().follow_loaded_files();
+        if let Some(t) = time {
+            current_time_entry.buffer().set_text(format!("{:.3}", t).as_str());
+        }
+        Continue(true)
+    }));
+
     // Custom update routine (called every 10 ms)
     let status_mutex = Arc::new(Mutex::new(PlotStatus::Idle));
-    let state_clone = state_cell;
+    let state_clone = state_cell.clone();
     let time_entry_clone = current_time_entry;
     let x_min_entry_clone = x_min_entry;
     let x_max_entry_clone = x_max_entry;
@@ -175,6 +203,15 @@ pub fn build_ui(application: &Application, state_cell: Rc<RefCell<State>>) {
     });
 
     window.show_all();
+
+    // Problems with files given on the command line
+    show_load_report(&window, &state_cell);
+
+    // Simulation directories given on the command line
+    let pending_simulation = state_cell.borrow_mut().pending_simulation.take();
+    if let Some(directory) = pending_simulation {
+        choose_simulation_variables(&window, &state_cell, &directory);
+    }
 }
 
 fn setup_plot_area(builder: Builder, state_cell: Rc<RefCell<State>>) -> gtk::DrawingArea {
@@ -545,7 +582,7 @@ fn setup_autoscale_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) ->
     (autoscale_x_toggle, autoscale_y_toggle)
 }
 
-fn setup_logscale_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) -> (ToggleButton, ToggleButton) {
+fn setup_logscale_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) -> (ToggleButton, ToggleButton, ToggleButton) {
     let logscale_x_toggle: ToggleButton = builder.object("logscale_x_toggle")
         .expect("Failed to get logscale_x_toggle");
     logscale_x_toggle.connect_toggled(clone!(@strong logscale_x_toggle,
@@ -565,10 +602,20 @@ fn setup_logscale_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) -> (
     }));
     logscale_y_toggle.set_active(false);
 
-    (logscale_x_toggle, logscale_y_toggle)
+    // Logscale color toggle setup, for heatmaps
+    let logscale_color_toggle: ToggleButton = builder.object("logscale_color_toggle")
+        .expect("Failed to get logscale_color_toggle");
+    logscale_color_toggle.connect_toggled(clone!(@strong logscale_color_toggle,
+                                                 @weak state_cell => move |_| {
+        state_cell.borrow_mut().plot_settings.use_logscale_color = logscale_color_toggle.is_active();
+        state_cell.borrow_mut().update_needed = true;
+    }));
+    logscale_color_toggle.set_active(false);
+
+    (logscale_x_toggle, logscale_y_toggle, logscale_color_toggle)
 }
 
-fn setup_style_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) -> (ToggleButton, ToggleButton, ToggleButton) {
+fn setup_style_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) -> (ToggleButton, ToggleButton, ToggleButton, ToggleButton) {
     let line_toggle: ToggleButton = builder.object("line_toggle")
         .expect("Failed to get line_toggle");
     line_toggle.connect_toggled(clone!(@strong line_toggle,
@@ -597,7 +644,108 @@ fn setup_style_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) -> (Tog
     }));
     color_toggle.set_active(true);
 
-    (line_toggle, point_toggle, color_toggle)
+    // Composite toggle, merging refinement levels and components into one curve per step
+    let composite_toggle: ToggleButton = builder.object("composite_toggle")
+        .expect("Failed to get composite_toggle");
+    composite_toggle.connect_toggled(clone!(@strong composite_toggle,
+                                            @weak state_cell => move |_| {
+        state_cell.borrow_mut().plot_settings.composite_lines = composite_toggle.is_active();
+        state_cell.borrow_mut().update_needed = true;
+    }));
+    composite_toggle.set_active(false);
+
+    (line_toggle, point_toggle, color_toggle, composite_toggle)
+}
+
+fn setup_channel_combos(builder: Builder, state_cell: Rc<RefCell<State>>) -> (ComboBoxText, ComboBoxText) {
+    let complex_part_combo: ComboBoxText = builder.object("complex_part_combo")
+        .expect("Failed to get complex_part_combo");
+    complex_part_combo.connect_changed(clone!(@weak state_cell => move |combo| {
+        let part = match combo.active_id().as_deref() {
+            Some("phase") => ComplexPart::Phase,
+            Some("real") => ComplexPart::Real,
+            Some("imaginary") => ComplexPart::Imaginary,
+            _ => ComplexPart::Magnitude,
+        };
+        state_cell.borrow_mut().plot_settings.complex_part = part;
+        state_cell.borrow_mut().update_needed = true;
+    }));
+    complex_part_combo.set_active_id(Some("magnitude"));
+
+    let uncertainty_combo: ComboBoxText = builder.object("uncertainty_combo")
+        .expect("Failed to get uncertainty_combo");
+    uncertainty_combo.connect_changed(clone!(@weak state_cell => move |combo| {
+        let style = match combo.active_id().as_deref() {
+            Some("band") => UncertaintyStyle::Band,
+            Some("hidden") => UncertaintyStyle::Hidden,
+            _ => UncertaintyStyle::Bars,
+        };
+        state_cell.borrow_mut().plot_settings.uncertainty_style = style;
+        state_cell.borrow_mut().update_needed = true;
+    }));
+    uncertainty_combo.set_active_id(Some("bars"));
+
+    (complex_part_combo, uncertainty_combo)
+}
+
+fn setup_contour_widgets(builder: Builder, state_cell: Rc<RefCell<State>>) -> (Entry, ToggleButton) {
+    let contour_levels_entry: Entry = builder.object("contour_levels_entry")
+        .expect("Failed to get contour_levels_entry");
+    contour_levels_entry.connect_activate(clone!(@weak state_cell => move |entry| {
+        // Text that cannot be read removes the contours
+        let levels = ContourLevels::parse(&entry.buffer().text());
+        if levels.is_none() {
+            entry.buffer().set_text("");
+        }
+        state_cell.borrow_mut().plot_settings.contour_levels = levels;
+        state_cell.borrow_mut().update_needed = true;
+    }));
+
+    // Heatmap toggle, leaving only the contour lines when inactive
+    let heatmap_toggle: ToggleButton = builder.object("heatmap_toggle")
+        .expect("Failed to get heatmap_toggle");
+    heatmap_toggle.connect_toggled(clone!(@strong heatmap_toggle,
+                                          @weak state_cell => move |_| {
+        state_cell.borrow_mut().plot_settings.draw_heatmap = heatmap_toggle.is_active();
+        state_cell.borrow_mut().update_needed = true;
+    }));
+    heatmap_toggle.set_active(true);
+
+    (contour_levels_entry, heatmap_toggle)
+}
+
+fn setup_time_series_toggle(builder: Builder, state_cell: Rc<RefCell<State>>) -> ToggleButton {
+    let time_series_toggle: ToggleButton = builder.object("time_series_toggle")
+        .expect("Failed to get time_series_toggle");
+    time_series_toggle.connect_toggled(clone!(@strong time_series_toggle,
+                                              @weak state_cell => move |_| {
+        state_cell.borrow_mut().show_time_series = time_series_toggle.is_active();
+        state_cell.borrow_mut().update_needed = true;
+    }));
+    time_series_toggle.set_active(false);
+
+    time_series_toggle
+}
+
+fn setup_follow_toggles(builder: Builder, state_cell: Rc<RefCell<State>>) -> (ToggleButton, ToggleButton) {
+    let follow_toggle: ToggleButton = builder.object("follow_toggle")
+        .expect("Failed to get follow_toggle");
+    follow_toggle.connect_toggled(clone!(@strong follow_toggle,
+                                         @weak state_cell => move |_| {
+        state_cell.borrow_mut().follow_files = follow_toggle.is_active();
+    }));
+    follow_toggle.set_active(false);
+
+    // Jump to newest toggle setup
+    let follow_jump_toggle: ToggleButton = builder.object("follow_jump_toggle")
+        .expect("Failed to get follow_jump_toggle");
+    follow_jump_toggle.connect_toggled(clone!(@strong follow_jump_toggle,
+                                              @weak state_cell => move |_| {
+        state_cell.borrow_mut().follow_jump_to_newest = follow_jump_toggle.is_active();
+    }));
+    follow_jump_toggle.set_active(false);
+
+    (follow_toggle, follow_jump_toggle)
 }
 
 fn setup_plot_range_entries(builder: Builder, state_cell: Rc<RefCell<State>>, toggles: (ToggleButton, ToggleButton)) -> (Entry, Entry, Entry, Entry) {
@@ -663,24 +811,308 @@ fn setup_load_button(builder: Builder, state_cell: Rc<RefCell<State>>, window: A
         .expect("Failed to get load_button");
     load_button.connect_clicked(clone!(@strong window,
                                        @weak state_cell => move |_| {
-        let file_chooser_dialog = FileChooserDialog::new(Some("Load Data"), Some(&window), gtk::FileChooserAction::Open);
+        choose_data_files(&window, &state_cell, false);
+    }));
+
+    load_button
+}
+
+fn setup_add_button(builder: Builder, state_cell: Rc<RefCell<State>>, window: ApplicationWindow) -> Button {
+    let add_button: Button = builder.object("add_button")
+        .expect("Failed to get add_button");
+    add_button.connect_clicked(clone!(@strong window,
+                                      @weak state_cell => move |_| {
+        choose_data_files(&window, &state_cell, true);
+    }));
+
+    add_button
+}
+
+/// Lets the user pick data files, and loads them in place of the loaded data or adds them to it
+fn choose_data_files(window: &ApplicationWindow, state_cell: &Rc<RefCell<State>>, add: bool) {
+    let title = if add { "Add Data" } else { "Load Data" };
+    let file_chooser_dialog = FileChooserDialog::new(Some(title), Some(window), gtk::FileChooserAction::Open);
+    file_chooser_dialog.add_button("Cancel", ResponseType::Cancel);
+    file_chooser_dialog.add_button("Open", ResponseType::Accept);
+    file_chooser_dialog.set_select_multiple(true);
+    let (load_settings_box, settings_widgets) = load_settings_widgets(state_cell.clone());
+    file_chooser_dialog.set_extra_widget(&load_settings_box);
+    file_chooser_dialog.connect_response(clone!(@strong window,
+                                                @strong state_cell => move |d,r| {
+        if let ResponseType::Accept = r {
+            let filenames = d.filenames();
+            let filenames: Vec<String> = filenames.iter().map(|pb| pb.as_path().display().to_string()).collect();
+            settings_widgets.update_settings(&mut state_cell.borrow_mut().load_settings);
+            if add {
+                state_cell.borrow_mut().add_files(filenames);
+            } else {
+                state_cell.borrow_mut().load_files(filenames);
+            }
+            d.hide();
+            show_load_report(&window, &state_cell);
+        }
+    }));
+    file_chooser_dialog.run();
+    file_chooser_dialog.hide();
+}
+
+fn setup_open_simulation_button(builder: Builder, state_cell: Rc<RefCell<State>>, window: ApplicationWindow) -> Button {
+    let open_simulation_button: Button = builder.object("open_simulation_button")
+        .expect("Failed to get open_simulation_button");
+    open_simulation_button.connect_clicked(clone!(@strong window,
+                                                  @weak state_cell => move |_| {
+        let file_chooser_dialog = FileChooserDialog::new(Some("Open Simulation"), Some(&window), gtk::FileChooserAction::SelectFolder);
         file_chooser_dialog.add_button("Cancel", ResponseType::Cancel);
         file_chooser_dialog.add_button("Open", ResponseType::Accept);
-        file_chooser_dialog.set_select_multiple(true);
-        file_chooser_dialog.connect_response(move |d,r| {
-            if let ResponseType::Accept = r {
-                let filenames = d.filenames();
-                let filenames: Vec<String> = filenames.iter().map(|pb| pb.as_path().display().to_string()).collect();
-                if let Some(data) = Data::from_files(filenames) {
-                    state_cell.borrow_mut().load_data(data);
-                }
-            }
-        });
-        file_chooser_dialog.run();
+        let response = file_chooser_dialog.run();
         file_chooser_dialog.hide();
+        if let (ResponseType::Accept, Some(directory)) = (response, file_chooser_dialog.filename()) {
+            choose_simulation_variables(&window, &state_cell, &directory);
+        }
     }));
 
-    load_button
+    open_simulation_button
+}
+
+/// Lets the user pick variables of a SimFactory simulation, and loads them stitched together
+/// from all of its output-NNNN segments
+fn choose_simulation_variables(window: &ApplicationWindow, state_cell: &Rc<RefCell<State>>, directory: &std::path::Path) {
+    use crate::simfactory::discover_variables;
+
+    let variables = discover_variables(directory);
+    if variables.is_empty() {
+        let dialog = MessageDialog::new(Some(window), gtk::DialogFlags::MODAL, gtk::MessageType::Warning,
+                                        gtk::ButtonsType::Ok, "No simulation data found");
+        dialog.set_secondary_text(Some(&format!("{} has no output-NNNN directories with data files", directory.display())));
+        dialog.run();
+        dialog.hide();
+        return;
+    }
+
+    let dialog = gtk::Dialog::with_buttons(Some("Load Variables"), Some(window), gtk::DialogFlags::MODAL,
+                                           &[("Cancel", ResponseType::Cancel), ("Load", ResponseType::Accept)]);
+    let list_box = gtk::ListBox::new();
+    list_box.set_selection_mode(gtk::SelectionMode::Multiple);
+    for variable in &variables {
+        let label = gtk::Label::new(Some(&format!("{} ({} segments)", variable.name, variable.files.len())));
+        label.set_xalign(0.0);
+        list_box.add(&label);
+    }
+    let scrolled_window = gtk::ScrolledWindow::builder()
+        .min_content_width(400)
+        .min_content_height(300)
+        .build();
+    scrolled_window.add(&list_box);
+    dialog.content_area().pack_start(&scrolled_window, true, true, 0);
+    dialog.show_all();
+
+    let response = dialog.run();
+    let selected: Vec<usize> = list_box.selected_rows().iter().map(|row| row.index() as usize).collect();
+    dialog.hide();
+
+    if response == ResponseType::Accept && !selected.is_empty() {
+        let filenames = selected.iter().flat_map(|i| variables[*i].files.clone()).collect();
+        state_cell.borrow_mut().load_simulation(filenames);
+        show_load_report(window, state_cell);
+    }
+}
+
+/// Shows the problems found during the last load and how overlapping restarts were merged, if there
+/// was anything to report. Long reports are cut short, the full report is on stderr.
+fn show_load_report(window: &ApplicationWindow, state_cell: &Rc<RefCell<State>>) {
+    const MAX_SHOWN: usize = 20;
+
+    let report = match state_cell.borrow_mut().load_report.take() {
+        Some(report) => report,
+        None => return,
+    };
+
+    let lines: Vec<String> = report.errors.iter().map(|e| e.to_string())
+        .chain(report.restarts.iter().map(|o| format!("Restart overlap: {}", o)))
+        .collect();
+    let mut text: Vec<String> = lines.iter().take(MAX_SHOWN).cloned().collect();
+    if lines.len() > MAX_SHOWN {
+        text.push(format!("... and {} more", lines.len() - MAX_SHOWN));
+    }
+
+    let (message_type, title) = if report.errors.is_empty() {
+        (gtk::MessageType::Info, "Overlapping restarts were merged")
+    } else {
+        (gtk::MessageType::Warning, "Some data could not be read")
+    };
+    let dialog = MessageDialog::new(Some(window), gtk::DialogFlags::MODAL, message_type,
+                                    gtk::ButtonsType::Ok, title);
+    dialog.set_secondary_text(Some(&text.join("\n")));
+    dialog.run();
+    dialog.hide();
+}
+
+/// Widgets for the options of reading files, shown in the load dialog
+struct LoadSettingsWidgets {
+    time_column_entry: Entry,
+    x_column_entry: Entry,
+    y_columns_entry: Entry,
+    imaginary_columns_entry: Entry,
+    uncertainty_columns_entry: Entry,
+    relative_tolerance_entry: Entry,
+    absolute_tolerance_entry: Entry,
+    filter_start_entry: Entry,
+    filter_end_entry: Entry,
+    filter_stride_entry: Entry,
+    merge_restarts_check_button: CheckButton,
+    on_demand_check_button: CheckButton,
+}
+
+impl LoadSettingsWidgets {
+    /// Reads the options as entered. Entries that can not be parsed leave the option as it was.
+    fn update_settings(&self, settings: &mut LoadSettings) {
+        settings.columns = ColumnSelection {
+            time: Column::parse(&self.time_column_entry.text()),
+            x: Column::parse(&self.x_column_entry.text()),
+            y: self.y_columns_entry.text().split(',').filter_map(Column::parse).collect(),
+            imaginary: self.imaginary_columns_entry.text().split(',').filter_map(Column::parse).collect(),
+            uncertainty: self.uncertainty_columns_entry.text().split(',').filter_map(Column::parse).collect(),
+        };
+        if let Ok(relative) = self.relative_tolerance_entry.text().trim().parse::<f64>() {
+            settings.time_tolerance.relative = relative.abs();
+        }
+        if let Ok(absolute) = self.absolute_tolerance_entry.text().trim().parse::<f64>() {
+            settings.time_tolerance.absolute = absolute.abs();
+        }
+        // An empty time entry leaves that end of the window open
+        settings.time_filter.start = self.filter_start_entry.text().trim().parse::<f64>().ok();
+        settings.time_filter.end = self.filter_end_entry.text().trim().parse::<f64>().ok();
+        if let Ok(stride) = self.filter_stride_entry.text().trim().parse::<usize>() {
+            settings.time_filter.stride = stride.max(1);
+        }
+        settings.merge_restarts = self.merge_restarts_check_button.is_active();
+        settings.on_demand = self.on_demand_check_button.is_active();
+    }
+}
+
+/// Entries for picking the time, x and y columns of files with several columns, by number or by name.
+/// Several y columns can be given, separated by commas, and so can the columns of their imaginary
+/// parts and uncertainties. Empty entries let the reader decide.
+/// Also entries for how close times of different lines must be to belong to the same step, for the
+/// window of time and stride of steps to read, and check buttons for merging restarted runs and for
+/// reading steps on demand, for datasets too large to keep in memory.
+fn load_settings_widgets(state_cell: Rc<RefCell<State>>) -> (gtk::Box, LoadSettingsWidgets) {
+    let settings = state_cell.borrow().load_settings.clone();
+    let columns = settings.columns;
+
+    let time_column_entry = Entry::new();
+    time_column_entry.set_width_chars(8);
+    if let Some(column) = &columns.time {
+        time_column_entry.set_text(&column.to_string());
+    }
+    let x_column_entry = Entry::new();
+    x_column_entry.set_width_chars(8);
+    if let Some(column) = &columns.x {
+        x_column_entry.set_text(&column.to_string());
+    }
+    let y_columns_entry = Entry::new();
+    y_columns_entry.set_width_chars(16);
+    y_columns_entry.set_text(&column_list(&columns.y));
+    let imaginary_columns_entry = Entry::new();
+    imaginary_columns_entry.set_width_chars(8);
+    imaginary_columns_entry.set_text(&column_list(&columns.imaginary));
+    let uncertainty_columns_entry = Entry::new();
+    uncertainty_columns_entry.set_width_chars(8);
+    uncertainty_columns_entry.set_text(&column_list(&columns.uncertainty));
+
+    let column_selection_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+    column_selection_box.set_tooltip_text(Some("Several y columns, separated by commas, are loaded as separate series. \
+                                                Columns of files without column names, e.g. ygraph files, are given by number."));
+    column_selection_box.pack_start(&gtk::Label::new(Some("Columns (empty = auto), time:")), false, false, 0);
+    column_selection_box.pack_start(&time_column_entry, false, false, 0);
+    column_selection_box.pack_start(&gtk::Label::new(Some("x:")), false, false, 0);
+    column_selection_box.pack_start(&x_column_entry, false, false, 0);
+    column_selection_box.pack_start(&gtk::Label::new(Some("y:")), false, false, 0);
+    column_selection_box.pack_start(&y_columns_entry, false, false, 0);
+
+    let channel_selection_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+    channel_selection_box.set_tooltip_text(Some("Columns of the imaginary parts and of the uncertainties σ of the y columns, \
+                                                 in the same order as those"));
+    channel_selection_box.pack_start(&gtk::Label::new(Some("Imaginary part:")), false, false, 0);
+    channel_selection_box.pack_start(&imaginary_columns_entry, false, false, 0);
+    channel_selection_box.pack_start(&gtk::Label::new(Some("σ:")), false, false, 0);
+    channel_selection_box.pack_start(&uncertainty_columns_entry, false, false, 0);
+
+    let relative_tolerance_entry = Entry::new();
+    relative_tolerance_entry.set_width_chars(8);
+    relative_tolerance_entry.set_text(&settings.time_tolerance.relative.to_string());
+    let absolute_tolerance_entry = Entry::new();
+    absolute_tolerance_entry.set_width_chars(8);
+    absolute_tolerance_entry.set_text(&settings.time_tolerance.absolute.to_string());
+
+    let tolerance_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+    tolerance_box.set_tooltip_text(Some("Lines whose times differ by no more than this belong to the same step"));
+    tolerance_box.pack_start(&gtk::Label::new(Some("Time tolerance, relative:")), false, false, 0);
+    tolerance_box.pack_start(&relative_tolerance_entry, false, false, 0);
+    tolerance_box.pack_start(&gtk::Label::new(Some("absolute:")), false, false, 0);
+    tolerance_box.pack_start(&absolute_tolerance_entry, false, false, 0);
+
+    let optional_time_entry = |time: Option<f64>| {
+        let entry = Entry::new();
+        entry.set_width_chars(8);
+        if let Some(time) = time {
+            entry.set_text(&time.to_string());
+        }
+        entry
+    };
+    let filter_start_entry = optional_time_entry(settings.time_filter.start);
+    let filter_end_entry = optional_time_entry(settings.time_filter.end);
+    let filter_stride_entry = Entry::new();
+    filter_stride_entry.set_width_chars(4);
+    filter_stride_entry.set_text(&settings.time_filter.stride.to_string());
+
+    let filter_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
+    filter_box.set_tooltip_text(Some("Only read steps in this window of time (empty = open), and of those every n-th"));
+    filter_box.pack_start(&gtk::Label::new(Some("Read times from:")), false, false, 0);
+    filter_box.pack_start(&filter_start_entry, false, false, 0);
+    filter_box.pack_start(&gtk::Label::new(Some("to:")), false, false, 0);
+    filter_box.pack_start(&filter_end_entry, false, false, 0);
+    filter_box.pack_start(&gtk::Label::new(Some("every n-th step, n:")), false, false, 0);
+    filter_box.pack_start(&filter_stride_entry, false, false, 0);
+
+    let merge_restarts_check_button = CheckButton::with_label("Merge restarted runs");
+    merge_restarts_check_button.set_active(settings.merge_restarts);
+    merge_restarts_check_button.set_tooltip_text(Some("Where output directories overlap in time, keep only the newer one"));
+
+    let on_demand_check_button = CheckButton::with_label("Read steps on demand");
+    on_demand_check_button.set_active(settings.on_demand);
+    on_demand_check_button.set_tooltip_text(Some("Only index files when loading, and read each step when it is shown"));
+
+    let load_settings_box = gtk::Box::new(gtk::Orientation::Vertical, 5);
+    load_settings_box.pack_start(&column_selection_box, false, false, 0);
+    load_settings_box.pack_start(&channel_selection_box, false, false, 0);
+    load_settings_box.pack_start(&tolerance_box, false, false, 0);
+    load_settings_box.pack_start(&filter_box, false, false, 0);
+    load_settings_box.pack_start(&merge_restarts_check_button, false, false, 0);
+    load_settings_box.pack_start(&on_demand_check_button, false, false, 0);
+    load_settings_box.show_all();
+
+    let widgets = LoadSettingsWidgets {
+        time_column_entry,
+        x_column_entry,
+        y_columns_entry,
+        imaginary_columns_entry,
+        uncertainty_columns_entry,
+        relative_tolerance_entry,
+        absolute_tolerance_entry,
+        filter_start_entry,
+        filter_end_entry,
+        filter_stride_entry,
+        merge_restarts_check_button,
+        on_demand_check_button,
+    };
+    (load_settings_box, widgets)
+}
+
+/// Columns as entered, separated by commas
+fn column_list(columns: &[Column]) -> String {
+    let columns: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+    columns.join(", ")
 }
 
 fn setup_save_button(builder: Builder, state_cell: Rc<RefCell<State>>, window: ApplicationWindow) -> Button {
@@ -721,8 +1153,8 @@ fn setup_export_gnuplot_button(builder: Builder, state_cell: Rc<RefCell<State>>,
             if let ResponseType::Accept = r {
                 if let Some(filename) = d.filename() {
                     let filename = filename.as_path().display().to_string();
-                    if let Some(dataslice) = &state_cell.borrow().current_slice {
-                        let gnuplot_string = dataslice.to_string_gnuplot();
+                    if let Some(series_slices) = &state_cell.borrow().current_slices {
+                        let gnuplot_string = to_string_gnuplot(series_slices);
                         std::fs::write(filename, gnuplot_string).expect("Failed to write file");
                     }
                 }
@@ -978,13 +1410,15 @@ fn plot_coords_from_image_coords(xi: f64, yi: f64, state_cell: Rc<RefCell<State>
         return (0.0, 0.0)
     };
 
-    // Images generated by plotters have margins on the left and bottom
-    const MARGIN_LEFT: f64 = 50.0;
-    const MARGIN_BOTTOM: f64 = 21.0;
+    // Images generated by plotters have margins on the left and bottom, heatmaps also have their
+    // colorbar on the right
+    const MARGIN_LEFT: f64 = Y_LABEL_AREA_SIZE as f64;
+    const MARGIN_BOTTOM: f64 = X_LABEL_AREA_SIZE as f64 + 1.0;
+    let margin_right = if state_cell.borrow().shows_heatmap() { COLORBAR_AREA_SIZE as f64 } else { 0.0 };
 
     // Adjust image coordinates and extents to take margins into account
     // Also clip selection to the plot area
-    let image_width = image_width - MARGIN_LEFT;
+    let image_width = image_width - MARGIN_LEFT - margin_right;
     let image_height = image_height - MARGIN_BOTTOM;
     let xi = (xi - MARGIN_LEFT).max(0.0);
     let yi = yi.max(0.0);